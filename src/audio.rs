@@ -0,0 +1,32 @@
+//! Sound events raised by the simulation.
+//!
+//! Systems push `SoundEvent`s onto the `SoundQueue` resource and the scene
+//! drains it once a frame, so game logic never has to touch ggez's audio.
+
+use crate::types::Point2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Sound {
+    /// Tuut tuut.
+    Horn,
+    /// A kiwi getting hit.
+    Squawk,
+}
+
+#[derive(Debug, Clone)]
+pub struct SoundEvent {
+    pub sound: Sound,
+    /// Where in the world the sound came from, for panning and falloff.
+    pub position: Point2,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SoundQueue {
+    pub events: Vec<SoundEvent>,
+}
+
+impl SoundQueue {
+    pub fn play(&mut self, sound: Sound, position: Point2) {
+        self.events.push(SoundEvent { sound, position });
+    }
+}
@@ -0,0 +1,209 @@
+//! A series of races, with points for each finishing position.
+//!
+//! The series is defined in a TOML file in the resource directory, e.g.
+//!
+//! ```toml
+//! name = "Sand Cup"
+//! points = [10, 6, 4, 3, 2, 1]
+//!
+//! [[races]]
+//! map = "map1.tmx"
+//! laps = 3
+//! ```
+//!
+//! The standings are saved to the user's data directory after every
+//! race, so a championship can be picked up again later.
+
+use ggez::{self, filesystem, GameResult};
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::race::{RaceConfig, RaceResult};
+use crate::util::{read_toml, write_toml};
+
+/// Where the standings are saved, in ggez's filesystem.
+const STANDINGS_PATH: &str = "/standings.toml";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChampionshipDef {
+    pub name: String,
+    /// Points for first place, second place and so on.
+    pub points: Vec<u32>,
+    pub races: Vec<RaceDef>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RaceDef {
+    /// Relative to the resource directory.
+    pub map: String,
+    pub laps: i32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Standing {
+    pub name: String,
+    pub points: u32,
+    pub wins: u32,
+}
+
+/// How the championship is going.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Standings {
+    /// Which championship these are for.
+    pub championship: String,
+    /// How many races have been run.
+    pub round: usize,
+    /// Best first.
+    pub entries: Vec<Standing>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Championship {
+    pub def: ChampionshipDef,
+    pub standings: Standings,
+}
+
+impl Championship {
+    /// Reads the championship definition, and picks up where the saved
+    /// standings left off if they are for the same championship.
+    pub fn load(ctx: &mut ggez::Context, path: &str) -> GameResult<Self> {
+        let def: ChampionshipDef = read_toml(ctx, path)?;
+        let standings = match read_toml::<Standings>(ctx, STANDINGS_PATH) {
+            Ok(ref standings) if standings.championship == def.name && standings.round < def.races.len() => {
+                info!("Resuming {} at race {}", def.name, standings.round + 1);
+                standings.clone()
+            }
+            _ => Standings {
+                championship: def.name.clone(),
+                ..Standings::default()
+            },
+        };
+        Ok(Championship { def, standings })
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.standings.round >= self.def.races.len()
+    }
+
+    /// The settings for the next race, if there is one.
+    pub fn race_config(&self) -> Option<RaceConfig> {
+        self.def.races.get(self.standings.round).map(|race| RaceConfig {
+            map: race.map.clone(),
+            laps: race.laps,
+            ..RaceConfig::default()
+        })
+    }
+
+    /// Hands out points for a race and moves on to the next one.
+    /// Returns the points each finisher got, in finishing order.
+    pub fn score(&mut self, result: &RaceResult) -> Vec<u32> {
+        let mut awarded = Vec::new();
+        for (position, finisher) in result.finishers.iter().enumerate() {
+            // cars that didn't finish get nothing
+            let points = match finisher.time {
+                Some(_) => self.def.points.get(position).cloned().unwrap_or(0),
+                None => 0,
+            };
+            awarded.push(points);
+
+            let entries = &mut self.standings.entries;
+            let index = match entries.iter().position(|e| e.name == finisher.name) {
+                Some(index) => index,
+                None => {
+                    entries.push(Standing { name: finisher.name.clone(), points: 0, wins: 0 });
+                    entries.len() - 1
+                }
+            };
+            entries[index].points += points;
+            if position == 0 && finisher.time.is_some() {
+                entries[index].wins += 1;
+            }
+        }
+        self.standings.entries.sort_by(|a, b| b.points.cmp(&a.points).then(b.wins.cmp(&a.wins)));
+        self.standings.round += 1;
+        awarded
+    }
+
+    pub fn save(&self, ctx: &mut ggez::Context) {
+        if let Err(e) = write_toml(ctx, STANDINGS_PATH, &self.standings) {
+            warn!("Couldn't save standings: {}", e);
+        }
+    }
+
+    /// Deletes the saved standings, once the championship is over.
+    pub fn forget(ctx: &mut ggez::Context) {
+        if filesystem::exists(ctx, STANDINGS_PATH) {
+            if let Err(e) = filesystem::delete(ctx, STANDINGS_PATH) {
+                warn!("Couldn't delete standings: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race::Finisher;
+
+    fn championship(points: &[u32], races: usize) -> Championship {
+        let def = ChampionshipDef {
+            name: String::from("Test Cup"),
+            points: points.to_vec(),
+            races: (0..races).map(|_| RaceDef { map: String::from("map1.tmx"), laps: 2 }).collect(),
+        };
+        Championship { def, standings: Standings::default() }
+    }
+
+    /// A race result with the cars in the given order, `None` for ones
+    /// that didn't finish.
+    fn result(cars: &[(&str, Option<f32>)]) -> RaceResult {
+        let finishers = cars
+            .iter()
+            .map(|&(name, time)| Finisher { name: name.to_owned(), player: false, time, penalty: 0.0, laps: Vec::new() })
+            .collect();
+        RaceResult { map: String::from("map1.tmx"), finishers, new_record: false }
+    }
+
+    fn points(championship: &Championship, name: &str) -> (u32, u32) {
+        let entry = championship.standings.entries.iter().find(|e| e.name == name).unwrap();
+        (entry.points, entry.wins)
+    }
+
+    #[test]
+    fn cars_that_dont_finish_score_nothing() {
+        let mut championship = championship(&[10, 6, 4], 1);
+        let awarded = championship.score(&result(&[("A", Some(60.0)), ("B", Some(61.0)), ("C", None)]));
+        assert_eq!(awarded, vec![10, 6, 0]);
+        assert_eq!(points(&championship, "C"), (0, 0));
+    }
+
+    #[test]
+    fn nobody_wins_when_nobody_finishes() {
+        let mut championship = championship(&[10, 6], 1);
+        championship.score(&result(&[("A", None), ("B", None)]));
+        assert_eq!(points(&championship, "A"), (0, 0));
+    }
+
+    #[test]
+    fn ties_go_to_more_wins() {
+        let mut championship = championship(&[3, 3, 0], 2);
+        championship.score(&result(&[("X", Some(60.0)), ("A", Some(61.0)), ("B", Some(62.0))]));
+        championship.score(&result(&[("B", Some(60.0)), ("X", Some(61.0)), ("A", Some(62.0))]));
+        let order: Vec<&str> = championship.standings.entries.iter().map(|e| e.name.as_str()).collect();
+        // A and B both have 3 points, but B has won a race
+        assert_eq!(order, vec!["X", "B", "A"]);
+        assert_eq!(points(&championship, "X"), (6, 1));
+    }
+
+    #[test]
+    fn each_race_moves_on_a_round() {
+        let mut championship = championship(&[10], 2);
+        assert_eq!(championship.race_config().map(|c| c.laps), Some(2));
+        championship.score(&result(&[("A", Some(60.0))]));
+        assert_eq!(championship.standings.round, 1);
+        assert!(!championship.is_over());
+        championship.score(&result(&[("A", Some(60.0))]));
+        assert!(championship.is_over());
+        assert!(championship.race_config().is_none());
+    }
+}
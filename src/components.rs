@@ -1,9 +1,11 @@
+use crate::track::Checkpoint;
 use crate::types::*;
+use crate::util;
 use ggez::graphics;
 use ncollide2d as nc;
-use euclid;
 use specs::*;
 use specs_derive::*;
+use std::collections::VecDeque;
 
 // ///////////////////////////////////////////////////////////////////////
 // Components
@@ -21,10 +23,18 @@ pub struct Position {
 #[derive(Clone, Debug, Component)]
 #[storage(VecStorage)]
 pub struct Sprite {
+	pub image: SpriteImage,
 	pub clip: graphics::Rect,
 	pub scale: graphics::mint::Vector2<f32>
 }
 
+/// Which image a `Sprite` is cut from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpriteImage {
+    Cars,
+    Kiwi,
+}
+
 /// Motion in the game world.
 #[derive(Clone, Debug, Component)]
 #[storage(VecStorage)]
@@ -35,21 +45,364 @@ pub struct Motion {
     pub orientation: f32
 }
 
+/// What a vehicle's driver is asking for this tick; set from the
+/// player's input or by an `AiDriver`, and applied to `Motion` by the
+/// `VehicleSystem`.
+#[derive(Clone, Debug, Default, Component)]
+#[storage(VecStorage)]
+pub struct Controls {
+    /// -1.0 is full left, 1.0 is full right.
+    pub steering: f32,
+    /// -1.0 is full brake/reverse, 1.0 is full gas.
+    pub throttle: f32,
+    /// Held down to fire the car's `Weapon`, if it has one.
+    pub fire: bool,
+    /// Asks to be put back at the last checkpoint.
+    pub reset: bool,
+}
+
+/// Per-car multipliers on how fast it can go and how quickly it turns.
 #[derive(Clone, Debug, Component)]
 #[storage(VecStorage)]
-pub struct Collider {
-    pub handle: nc::pipeline::object::CollisionObjectSlabHandle
+pub struct Handling {
+    pub top_speed: f32,
+    pub steering: f32,
+}
+
+impl Default for Handling {
+    fn default() -> Self {
+        Handling { top_speed: 1.0, steering: 1.0 }
+    }
+}
+
+/// How much damage a vehicle can still take.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Health { current: max, max }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.max > 0.0 { self.current / self.max } else { 0.0 }
+    }
+}
+
+/// A vehicle whose health ran out.  It can't be driven until it
+/// respawns, `respawn_timer` seconds from now.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct Wrecked {
+    pub respawn_timer: f32,
+}
+
+/// Where a car goes back to when it is reset, wrecked or stuck: the last
+/// checkpoint it passed, or where it started.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct Respawn {
+    pub point: Point2,
+    pub orientation: f32,
+    /// Index of the checkpoint the car has to pass next.
+    pub next_checkpoint: usize,
+    /// Set to respawn the car on the next tick.
+    pub requested: bool,
+    /// Seconds the car has been standing still, and off the track.
+    pub stuck_time: f32,
+    pub off_track_time: f32,
 }
 
-/// Just a marker that a particular entity is the player.
+impl Respawn {
+    pub fn new(point: Point2, orientation: f32) -> Self {
+        Respawn {
+            point,
+            orientation,
+            next_checkpoint: 0,
+            requested: false,
+            stuck_time: 0.0,
+            off_track_time: 0.0,
+        }
+    }
+}
+
+/// Seconds until the entity can take damage again.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct Invulnerable {
+    pub remaining: f32,
+}
+
+/// How far a car has got around the track, measured along the racing line.
 #[derive(Clone, Debug, Default, Component)]
-#[storage(NullStorage)]
-pub struct Player;
+#[storage(VecStorage)]
+pub struct RaceProgress {
+    /// Starts at 0 and goes up each time the car crosses the start of the racing line.
+    pub lap: i32,
+    /// Distance along the racing line within the current lap.
+    pub distance: f32,
+    /// Laps and distance combined, for comparing cars.
+    pub total: f32,
+    pub started: bool,
+    /// Set once the car has been driving against the track direction for
+    /// a while; `wrong_way_time` counts how long, and
+    /// `wrong_way_total` adds up all the time spent wrong-way this race.
+    pub wrong_way: bool,
+    pub wrong_way_time: f32,
+    pub wrong_way_total: f32,
+    /// Seconds added to the race time for hitting pedestrians.
+    pub penalty: f32,
+    /// Seconds since the race started, stopped once the car finishes.
+    pub time: f32,
+    pub finished: bool,
+    /// The highest lap the car has started, and when it started it.
+    pub lap_reached: i32,
+    pub lap_start: f32,
+    /// Every lap the car has completed, in order.
+    pub laps: Vec<Lap>,
+}
+
+/// A completed lap.
+#[derive(Clone, Copy, Debug)]
+pub struct Lap {
+    pub time: f32,
+    /// False if track limits invalidated it.
+    pub valid: bool,
+}
 
+/// Time a car has spent off the road, and the penalties it picked up
+/// for it under the race's track limits.
 #[derive(Clone, Debug, Default, Component)]
 #[storage(VecStorage)]
+pub struct OffTrack {
+    /// Whether all four wheels are off the road right now, and for how
+    /// many seconds they have been.
+    pub off: bool,
+    pub time: f32,
+    /// All the time spent off the road this race.
+    pub total: f32,
+    /// Seconds added to the car's race time.
+    pub penalty: f32,
+    /// The lap that got invalidated, if the current one has.
+    pub invalid_lap: Option<i32>,
+    /// Fraction of its top speed the car is held to, if any.
+    pub speed_limit: Option<f32>,
+    /// Whether the car has moved yet.  Waiting on the grid never counts
+    /// as being off the track.
+    pub moved: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignalState {
+    Green,
+    Yellow,
+    Red,
+}
+
+/// A traffic light controlling cars that drive across its stop line in
+/// one direction.  It cycles through its phases over and over.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct TrafficSignal {
+    pub stop_line: Checkpoint,
+    /// Which way the cars the signal controls are driving.
+    pub direction: f32,
+    /// Each state the light shows, and for how many seconds.
+    pub phases: Vec<(SignalState, f32)>,
+    /// Seconds into the cycle.
+    pub time: f32,
+    /// Cars on the stop line, and the ones that drove onto it on red
+    /// this tick.
+    pub inside: Vec<Entity>,
+    pub ran_red: Vec<Entity>,
+}
+
+impl TrafficSignal {
+    pub fn cycle_length(&self) -> f32 {
+        self.phases.iter().map(|&(_, duration)| duration).sum()
+    }
+
+    pub fn state(&self) -> SignalState {
+        let mut time = self.time;
+        for &(state, duration) in &self.phases {
+            if time < duration {
+                return state;
+            }
+            time -= duration;
+        }
+        self.phases.last().map_or(SignalState::Green, |&(state, _)| state)
+    }
+
+    /// Whether a car facing `orientation` is one the signal controls.
+    pub fn controls(&self, orientation: f32) -> bool {
+        util::wrap_angle(orientation - self.direction).abs() < std::f32::consts::FRAC_PI_2
+    }
+
+    /// How far ahead of a car at `point` facing `orientation` the stop
+    /// line is, if the car is driving towards it.
+    pub fn distance_ahead(&self, point: Point2, orientation: f32) -> Option<f32> {
+        if !self.controls(orientation) {
+            return None;
+        }
+        let forward = util::heading(self.direction);
+        let right = util::vec2(-forward.y, forward.x);
+        let d = self.stop_line.position - point;
+        let reach = self.stop_line.half_width.max(self.stop_line.half_height);
+        let ahead = d.dot(forward);
+        if ahead > 0.0 && d.dot(right).abs() <= reach {
+            Some(ahead)
+        } else {
+            None
+        }
+    }
+}
+
+/// Someone (or some kiwi) that walks back and forth across the road.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct Pedestrian {
+    /// The side of the road it is on, and the side it crosses to.
+    pub from: Point2,
+    pub to: Point2,
+    /// How far across it is, from 0.0 to 1.0.
+    pub progress: f32,
+    /// Seconds before it sets off again.
+    pub wait: f32,
+    /// Walking speed, in pixels a second.
+    pub speed: f32,
+}
+
+impl Pedestrian {
+    pub fn new(from: Point2, to: Point2, wait: f32) -> Self {
+        Pedestrian { from, to, progress: 0.0, wait, speed: 40.0 }
+    }
+}
+
+/// How well an AI driver drives.
+#[derive(Clone, Debug)]
+pub struct AiProfile {
+    /// Seconds between the driver deciding what to do and doing it.
+    pub reaction_delay: f32,
+    /// 1.0 drives exactly on the racing line, lower values wander off it.
+    pub line_accuracy: f32,
+    /// 0.0 brakes early and gently for corners, 1.0 late and hard.
+    pub braking_aggression: f32,
+    /// Fraction of the car's top speed the driver will use.
+    pub top_speed_fraction: f32,
+}
+
+impl AiProfile {
+    pub fn easy() -> Self {
+        AiProfile { reaction_delay: 0.4, line_accuracy: 0.5, braking_aggression: 0.2, top_speed_fraction: 0.75 }
+    }
+
+    pub fn medium() -> Self {
+        AiProfile { reaction_delay: 0.2, line_accuracy: 0.8, braking_aggression: 0.5, top_speed_fraction: 0.9 }
+    }
+
+    pub fn hard() -> Self {
+        AiProfile { reaction_delay: 0.05, line_accuracy: 1.0, braking_aggression: 0.9, top_speed_fraction: 1.0 }
+    }
+}
+
+/// A computer driver that follows the track's racing line, aiming for
+/// the point `lookahead` pixels further along it.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct AiDriver {
+    pub profile: AiProfile,
+    pub lookahead: f32,
+    /// Decisions waiting out the profile's reaction delay.
+    pub pending: VecDeque<Controls>,
+    pub ticks: u32,
+    /// Sideways offset from the racing line, in pixels, positive to the
+    /// right; moves towards `target_lane` when avoiding or overtaking.
+    pub lane_offset: f32,
+    pub target_lane: f32,
+    /// Seconds the way ahead has been clear since leaving the racing line.
+    pub clear_time: f32,
+}
+
+impl AiDriver {
+    pub fn new(profile: AiProfile) -> Self {
+        AiDriver {
+            profile,
+            lookahead: 96.0,
+            pending: VecDeque::new(),
+            ticks: 0,
+            lane_offset: 0.0,
+            target_lane: 0.0,
+            clear_time: 0.0,
+        }
+    }
+}
+
+/// An ambient traffic car, pottering along one of the track's traffic lanes.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct Traffic {
+    /// Index into `Track::traffic_lanes`.
+    pub lane: usize,
+    pub cruise_speed: f32,
+    /// Seconds the player has been in the way.
+    pub blocked_time: f32,
+    /// Seconds until the horn can be used again.
+    pub honk_cooldown: f32,
+}
+
+impl Traffic {
+    pub fn new(lane: usize) -> Self {
+        Traffic { lane, cruise_speed: 0.8, blocked_time: 0.0, honk_cooldown: 0.0 }
+    }
+}
+
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct Collider {
+    pub handle: nc::pipeline::object::CollisionObjectSlabHandle
+}
+
+/// Marks an entity as driven by one of the local players.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct Player {
+    /// Which player, from 0; picks their input and part of the window.
+    pub id: usize,
+}
+
+/// A projectile.  It only hits things outside its owner's collision group.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
 pub struct Shot {
     pub damage: u32,
+    pub owner: Entity,
+}
+
+/// A gun bolted to a car.  `group` is a collision group of the car's own,
+/// which its shots ignore.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct Weapon {
+    pub group: usize,
+    /// Seconds until it can fire again.
+    pub cooldown: f32,
+}
+
+impl Weapon {
+    pub fn new(group: usize) -> Self {
+        Weapon { group, cooldown: 0.0 }
+    }
+}
+
+/// Seconds until the entity gets despawned.
+#[derive(Clone, Debug, Component)]
+#[storage(VecStorage)]
+pub struct Lifetime {
+    pub remaining: f32,
 }
 
 impl Motion {
@@ -59,11 +412,37 @@ impl Motion {
     }
 }
 
+/// Identifies a scene on the scene stack.
+pub type SceneId = u32;
+
+/// Which scene an entity belongs to.  The entity is deleted when the
+/// scene leaves the stack.
+#[derive(Clone, Copy, Debug, PartialEq, Component)]
+#[storage(VecStorage)]
+pub struct SceneTag {
+    pub scene: SceneId,
+}
+
 pub fn register_components(specs_world: &mut World) {
     specs_world.register::<Position>();
     specs_world.register::<Motion>();
+    specs_world.register::<Controls>();
+    specs_world.register::<Handling>();
+    specs_world.register::<RaceProgress>();
+    specs_world.register::<OffTrack>();
+    specs_world.register::<Health>();
+    specs_world.register::<Wrecked>();
+    specs_world.register::<Respawn>();
+    specs_world.register::<Invulnerable>();
+    specs_world.register::<AiDriver>();
+    specs_world.register::<Traffic>();
+    specs_world.register::<TrafficSignal>();
+    specs_world.register::<Pedestrian>();
     specs_world.register::<Collider>();
-    // specs_world.register::<Shot>();
+    specs_world.register::<Shot>();
+    specs_world.register::<Weapon>();
+    specs_world.register::<Lifetime>();
     specs_world.register::<Player>();
     specs_world.register::<Sprite>();
+    specs_world.register::<SceneTag>();
 }
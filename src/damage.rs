@@ -0,0 +1,26 @@
+//! Damage dealt during a tick, queued up for the damage system to apply.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DamageSource {
+    Shot,
+    /// Running into something.
+    Impact,
+}
+
+#[derive(Debug, Clone)]
+pub struct DamageEvent {
+    pub target: specs::Entity,
+    pub amount: f32,
+    pub source: DamageSource,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DamageQueue {
+    pub events: Vec<DamageEvent>,
+}
+
+impl DamageQueue {
+    pub fn push(&mut self, target: specs::Entity, amount: f32, source: DamageSource) {
+        self.events.push(DamageEvent { target, amount, source });
+    }
+}
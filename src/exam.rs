@@ -0,0 +1,78 @@
+//! The driving test: rule violations and the verdict.
+
+use crate::track::Rule;
+use crate::types::*;
+
+/// Reaching this many penalty points fails the test straight away.
+pub const FAIL_POINTS: u32 = 10;
+/// Seconds to finish the lap in, after which the test is failed.  Also
+/// ends the test on maps without a lap to drive.
+pub const TIME_LIMIT: f32 = 300.0;
+
+/// Something the player did wrong, and when and where they did it.
+#[derive(Clone, Debug)]
+pub struct Violation {
+    /// Seconds since the test started.
+    pub time: f32,
+    pub position: Point2,
+    pub rule: Rule,
+}
+
+impl Violation {
+    pub fn points(&self) -> u32 {
+        match self.rule {
+            Rule::SpeedLimit(_) => 3,
+            Rule::StopLine => 5,
+            Rule::Yield => 5,
+            Rule::NoEntry => 10,
+            Rule::RedLight => 10,
+            Rule::HitPedestrian => FAIL_POINTS,
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self.rule {
+            Rule::SpeedLimit(limit) => format!("Exceeded the {:.1} speed limit", limit),
+            Rule::StopLine => String::from("Didn't stop at a stop line"),
+            Rule::Yield => String::from("Failed to give way"),
+            Rule::NoEntry => String::from("Drove into a no entry zone"),
+            Rule::RedLight => String::from("Ran a red light"),
+            Rule::HitPedestrian => String::from("Hit a pedestrian"),
+        }
+    }
+}
+
+/// What the examiner has seen of one rule zone.
+#[derive(Clone, Debug, Default)]
+pub struct ZoneState {
+    /// Whether the car was inside the zone last tick.
+    pub inside: bool,
+    /// Whether the car has come to a stop since it entered.
+    pub stopped: bool,
+    /// Whether the car is already being marked down here, so a single
+    /// offence isn't counted every tick.
+    pub flagged: bool,
+}
+
+/// The test in progress.  Lives in the specs world as a resource and is
+/// updated by the `ExamSystem`.
+#[derive(Clone, Debug, Default)]
+pub struct Exam {
+    pub elapsed: f32,
+    pub violations: Vec<Violation>,
+    /// One for each of the track's rule zones.
+    pub zones: Vec<ZoneState>,
+    /// Whether the test ran past TIME_LIMIT.
+    pub timed_out: bool,
+    pub finished: bool,
+}
+
+impl Exam {
+    pub fn points(&self) -> u32 {
+        self.violations.iter().map(Violation::points).sum()
+    }
+
+    pub fn passed(&self) -> bool {
+        !self.timed_out && self.points() < FAIL_POINTS
+    }
+}
@@ -1,12 +1,17 @@
 //! Typedefs for input shortcuts.
 use ggez::event::*;
 use ggez_goodies::input;
+use log::*;
+
+use crate::settings::Keys;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Button {
     Select,
     Back,
     Menu,
+    Fire,
+    Reset,
     Quit,
 }
 
@@ -20,15 +25,156 @@ pub type Binding = input::InputBinding<Axis, Button>;
 pub type Event = input::InputEffect<Axis, Button>;
 pub type State = input::InputState<Axis, Button>;
 
-/// Create the default keybindings for our input state.
-pub fn create_input_binding() -> input::InputBinding<Axis, Button> {
+/// One tick's worth of a player's driving input, resolved from their
+/// input state.  This is what the level reads, whether the player is at
+/// this keyboard or on the other end of a network connection.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Frame {
+    pub steering: f32,
+    pub throttle: f32,
+    pub fire: bool,
+    /// Whether reset is held down, not just pressed.
+    pub reset: bool,
+}
+
+impl Frame {
+    /// Bytes in an encoded frame.
+    pub const SIZE: usize = 3;
+
+    pub fn from_state(state: &State) -> Self {
+        Frame {
+            steering: state.get_axis(Axis::Horz),
+            throttle: state.get_axis(Axis::Vert),
+            fire: state.get_button_down(Button::Fire),
+            reset: state.get_button_down(Button::Reset),
+        }
+    }
+
+    /// Packs the frame for sending, with the axes rounded to 1/127ths.
+    pub fn encode(&self) -> [u8; Frame::SIZE] {
+        let axis = |value: f32| (value.max(-1.0).min(1.0) * 127.0).round() as i8 as u8;
+        let buttons = (self.fire as u8) | (self.reset as u8) << 1;
+        [axis(self.steering), axis(self.throttle), buttons]
+    }
+
+    pub fn decode(bytes: &[u8]) -> Self {
+        let axis = |byte: u8| byte as i8 as f32 / 127.0;
+        Frame {
+            steering: axis(bytes[0]),
+            throttle: axis(bytes[1]),
+            fire: bytes[2] & 1 != 0,
+            reset: bytes[2] & 2 != 0,
+        }
+    }
+}
+
+/// How many players can play on one keyboard.
+pub const MAX_LOCAL_PLAYERS: usize = 4;
+
+/// Keys that can be bound to driving, looked up by their names in the
+/// settings.
+const BINDABLE_KEYS: &[KeyCode] = &[
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+    KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+    KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+    KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+    KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+    KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+    KeyCode::Up, KeyCode::Down, KeyCode::Left, KeyCode::Right,
+    KeyCode::Space, KeyCode::Tab, KeyCode::Return, KeyCode::Back,
+    KeyCode::LShift, KeyCode::RShift, KeyCode::LControl, KeyCode::RControl, KeyCode::LAlt, KeyCode::RAlt,
+    KeyCode::Comma, KeyCode::Period, KeyCode::Slash, KeyCode::Semicolon, KeyCode::Apostrophe,
+    KeyCode::LBracket, KeyCode::RBracket, KeyCode::Minus, KeyCode::Equals,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Home, KeyCode::End, KeyCode::PageUp, KeyCode::PageDown,
+];
+
+/// The name a key goes by in the settings.
+pub fn key_name(key: KeyCode) -> String {
+    format!("{:?}", key)
+}
+
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    BINDABLE_KEYS.iter().cloned().find(|&key| key_name(key) == name)
+}
+
+/// Whether the key can be used for driving.  The menu keys are kept
+/// for the menus.
+pub fn is_bindable(key: KeyCode) -> bool {
+    BINDABLE_KEYS.contains(&key) && ![KeyCode::C, KeyCode::X, KeyCode::Z].contains(&key)
+}
+
+/// Create the keybindings for each local player, in player order, from
+/// their keys in the settings.  Only the first player's binding has the
+/// menu buttons.
+pub fn create_input_bindings(controls: &[Keys]) -> Vec<Binding> {
+    let defaults = Keys::defaults();
+    (0..MAX_LOCAL_PLAYERS)
+        .map(|player| {
+            let binding = if player == 0 { create_menu_binding() } else { input::InputBinding::new() };
+            let keys = controls.get(player).unwrap_or(&defaults[player]);
+            bind_driving_keys(binding, keys, &defaults[player])
+        })
+        .collect()
+}
+
+/// Adds keybindings for driving: accelerate, brake, steer, fire and
+/// reset.  Keys that aren't recognised fall back to the defaults.
+fn bind_driving_keys(binding: Binding, keys: &Keys, defaults: &Keys) -> Binding {
+    let key = |name: &str, default: &str| {
+        key_from_name(name).unwrap_or_else(|| {
+            warn!("Unknown key {:?}, using {:?}", name, default);
+            key_from_name(default).expect("Default key isn't bindable")
+        })
+    };
+    binding
+        .bind_key_to_axis(key(&keys.accelerate, &defaults.accelerate), Axis::Vert, true)
+        .bind_key_to_axis(key(&keys.brake, &defaults.brake), Axis::Vert, false)
+        .bind_key_to_axis(key(&keys.left, &defaults.left), Axis::Horz, false)
+        .bind_key_to_axis(key(&keys.right, &defaults.right), Axis::Horz, true)
+        .bind_key_to_button(key(&keys.fire, &defaults.fire), Button::Fire)
+        .bind_key_to_button(key(&keys.reset, &defaults.reset), Button::Reset)
+}
+
+/// Create the keybindings for the menu buttons, which only the first
+/// player has.
+fn create_menu_binding() -> Binding {
     input::InputBinding::new()
-        .bind_key_to_axis(KeyCode::Up, Axis::Vert, true)
-        .bind_key_to_axis(KeyCode::Down, Axis::Vert, false)
-        .bind_key_to_axis(KeyCode::Left, Axis::Horz, false)
-        .bind_key_to_axis(KeyCode::Right, Axis::Horz, true)
         .bind_key_to_button(KeyCode::C, Button::Select)
         .bind_key_to_button(KeyCode::X, Button::Back)
         .bind_key_to_button(KeyCode::Z, Button::Menu)
         .bind_key_to_button(KeyCode::Escape, Button::Quit)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_round_trips() {
+        let frame = Frame { steering: -1.0, throttle: 0.5, fire: true, reset: false };
+        let decoded = Frame::decode(&frame.encode());
+        assert_eq!(decoded, Frame { throttle: decoded.throttle, ..frame });
+        assert!((decoded.throttle - 0.5).abs() < 1.0 / 127.0);
+
+        let buttons = Frame { reset: true, ..Frame::default() };
+        assert_eq!(Frame::decode(&buttons.encode()), buttons);
+    }
+
+    #[test]
+    fn frame_encoding_is_stable() {
+        // the lockstep keeps its own frames the way the peer decodes them,
+        // so encoding a decoded frame mustn't change it
+        let frame = Frame { steering: 0.3, throttle: -0.77, fire: false, reset: true };
+        let once = Frame::decode(&frame.encode());
+        assert_eq!(Frame::decode(&once.encode()), once);
+    }
+
+    #[test]
+    fn frame_axes_are_clamped() {
+        let frame = Frame { steering: 2.0, throttle: -3.0, ..Frame::default() };
+        let decoded = Frame::decode(&frame.encode());
+        assert_eq!(decoded, Frame { steering: 1.0, throttle: -1.0, ..frame });
+    }
+}
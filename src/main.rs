@@ -6,39 +6,104 @@ use std::path;
 
 use ggez::{self, *};
 
+mod audio;
+mod championship;
 mod components;
+mod damage;
+mod exam;
 mod input;
+mod net;
+mod parking;
+mod race;
+mod records;
 mod resources;
 mod scenes;
+mod settings;
 mod systems;
+mod track;
 mod types;
 mod util;
 mod world;
 
 struct MainState {
     scenes: scenes::Stack,
-    input_binding: input::Binding,
+    /// Set when racing someone over the network.
+    lockstep: Option<net::Lockstep>,
+    /// Whether a level was running last tick.
+    simulating: bool,
 }
 
 impl MainState {
-    fn new(ctx: &mut Context, resource_path: &path::Path) -> Self {
-        let world = world::World::new(resource_path);
+    fn new(
+        ctx: &mut Context,
+        resource_path: &path::Path,
+        settings: settings::Settings,
+        config: race::RaceConfig,
+        start_level: bool,
+        championship: Option<championship::Championship>,
+        lockstep: Option<net::Lockstep>,
+    ) -> Self {
+        let world = world::World::new(resource_path, settings);
         let mut scenestack = scenes::Stack::new(ctx, world);
-        let initial_scene = Box::new(scenes::level::LevelScene::new(ctx, &mut scenestack.world));
-        scenestack.push(initial_scene);
+        scenestack.push(Box::new(scenes::menu::MenuScene::new(config.clone())));
+
+        // a mode picked on the command line skips the menu, which is
+        // still there to go back to
+        if start_level {
+            let mut loading = scenes::loading::LoadingScene::new(&scenestack.world, config);
+            if let Some(championship) = championship {
+                loading = loading.with_championship(championship);
+            }
+            scenestack.push(Box::new(loading));
+        }
 
         Self {
-            input_binding: input::create_input_binding(),
             scenes: scenestack,
+            lockstep,
+            simulating: false,
         }
     }
 }
 
 impl event::EventHandler for MainState {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        const DESIRED_FPS: u32 = 60;
+        const DESIRED_FPS: u32 = systems::TICKS_PER_SECOND;
         while timer::check_update_time(ctx, DESIRED_FPS) {
-            self.scenes.world.input.update(timer::duration_to_f64(timer::delta(ctx)) as f32);
+            let dt = timer::duration_to_f64(timer::delta(ctx)) as f32;
+            for input in &mut self.scenes.world.inputs {
+                input.update(dt);
+            }
+
+            // over the network this machine only has one player, on the
+            // first binding, and the game waits until the other one's
+            // input is in.  Only a running level uses up frames, and
+            // each level starts from the first one, so both machines
+            // start it on the same one however long they take to load it.
+            let simulating = self.scenes.is_updating("LevelScene");
+            let world = &mut self.scenes.world;
+            world.waiting_for_peer = false;
+            world.peer_gone = false;
+            match self.lockstep {
+                Some(ref mut lockstep) if simulating => {
+                    if !self.simulating {
+                        lockstep.reset();
+                    }
+                    match lockstep.advance(input::Frame::from_state(&world.inputs[0])) {
+                        Some(frames) => world.frames = frames,
+                        None => {
+                            world.waiting_for_peer = true;
+                            world.peer_gone = lockstep.disconnected();
+                        }
+                    }
+                }
+                // just left the level, so the peer needn't wait for us
+                Some(ref lockstep) if self.simulating => {
+                    lockstep.quit();
+                    world.frames = world.inputs.iter().map(input::Frame::from_state).collect();
+                }
+                _ => world.frames = world.inputs.iter().map(input::Frame::from_state).collect(),
+            }
+            self.simulating = simulating;
             self.scenes.update(ctx);
         }
         self.scenes.world.resources.sync(ctx);
@@ -59,16 +124,25 @@ impl event::EventHandler for MainState {
         _keymod: event::KeyMods,
         _repeat: bool,
     ) {
-        if let Some(ev) = self.input_binding.resolve(keycode) {
-            self.scenes.input(ev, true);
-            self.scenes.world.input.update_effect(ev, true);
-        } else {
+        let world = &mut self.scenes.world;
+        if world.capturing_key {
+            world.capturing_key = false;
+            world.captured_key = Some(keycode);
+            return;
+        }
+        let effects = resolve_key(&world.input_bindings, keycode);
+        for &(player, ev) in &effects {
+            // only the first player gets to work the menus
+            if player == 0 {
+                self.scenes.input(ev, true);
+            }
+            self.scenes.world.inputs[player].update_effect(ev, true);
+        }
+        if effects.is_empty() {
             // manual workaround for CMD-Q on Mac not quitting the app
             // issue tracked in https://github.com/tomaka/winit/issues/41
-            if keycode == event::KeyCode::Q {
-                if cfg!(target_os = "macos") && _keymod.contains(event::KeyMods::LOGO) {
-                    event::quit(_ctx);
-                }
+            if keycode == event::KeyCode::Q && cfg!(target_os = "macos") && _keymod.contains(event::KeyMods::LOGO) {
+                event::quit(_ctx);
             }
 
         }
@@ -80,13 +154,24 @@ impl event::EventHandler for MainState {
         keycode: event::KeyCode,
         _keymod: event::KeyMods,
     ) {
-        if let Some(ev) = self.input_binding.resolve(keycode) {
-            self.scenes.input(ev, false);
-            self.scenes.world.input.update_effect(ev, false);
+        for (player, ev) in resolve_key(&self.scenes.world.input_bindings, keycode) {
+            if player == 0 {
+                self.scenes.input(ev, false);
+            }
+            self.scenes.world.inputs[player].update_effect(ev, false);
         }
     }
 }
 
+/// What a key does for each player whose binding has it.
+fn resolve_key(bindings: &[input::Binding], keycode: event::KeyCode) -> Vec<(usize, input::Event)> {
+    bindings
+        .iter()
+        .enumerate()
+        .filter_map(|(player, binding)| binding.resolve(keycode).map(|ev| (player, ev)))
+        .collect()
+}
+
 fn main() {
     util::setup_logging();
 
@@ -99,13 +184,61 @@ fn main() {
     };
     println!("Resource dir: {:?}", resource_dir);
 
-    let cb = ContextBuilder::new("game-template", "ggez")
-        .window_setup(conf::WindowSetup::default().title("game template"))
-        .window_mode(conf::WindowMode::default().dimensions(800.0, 600.0))
+    let settings = settings::Settings::load();
+    let cb = ContextBuilder::new(settings::GAME_ID, settings::AUTHOR)
+        .window_setup(conf::WindowSetup::default().title("game template").vsync(settings.video.vsync))
+        .window_mode(settings.window_mode())
         .add_resource_path(&resource_dir);
     let (ctx, ev) = &mut cb.build().unwrap();
+    // the game always draws an 800x600 view, scaled to the window
+    graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, 800.0, 600.0)).unwrap();
+
+    // `cargo run` opens the menu.  `cargo run -- parking` goes straight to
+    // the parking challenge, `cargo run -- exam` the driving test, and
+    // `cargo run -- race 2` a race with two players on one keyboard.
+    // `cargo run -- championship` runs the races in
+    // resources/championship.toml.  To race over the network, one machine
+    // runs `cargo run -- host <own address> <peer address>`
+    // and the other `cargo run -- join <own address> <peer address>`.
+    let args: Vec<String> = env::args().skip(1).collect();
+    let arg = |i: usize| args.get(i).map(String::as_str);
+    let mut config = race::RaceConfig::default();
+    let mut championship = None;
+    let mut lockstep = None;
+    let mut start_level = true;
+    match arg(0) {
+        Some("parking") => config = race::RaceConfig::parking(),
+        Some("championship") => {
+            let loaded = championship::Championship::load(ctx, "/championship.toml").expect("Couldn't load championship");
+            config = loaded.race_config().expect("Championship has no races");
+            championship = Some(loaded);
+        }
+        Some("exam") => config = race::RaceConfig::exam(),
+        Some(role @ "host") | Some(role @ "join") => {
+            let player = if role == "host" { 0 } else { 1 };
+            let address = |i: usize| -> std::net::SocketAddr {
+                arg(i)
+                    .and_then(|a| a.parse().ok())
+                    .unwrap_or_else(|| panic!("Usage: {} <own address> <peer address>, e.g. 127.0.0.1:7000 127.0.0.1:7001", role))
+            };
+            config.players = 2;
+            config.local_player = Some(player);
+            lockstep = Some(net::Lockstep::new(address(1), address(2), player).expect("Couldn't open network socket"));
+        }
+        Some("race") => {
+            if let Some(players) = arg(1).and_then(|a| a.parse::<usize>().ok()) {
+                config.players = players.max(1).min(input::MAX_LOCAL_PLAYERS);
+            }
+        }
+        None => start_level = false,
+        Some(other) => {
+            println!("Unknown mode {:?}, going to the menu.", other);
+            println!("Modes: race [players], parking, exam, championship, host or join <own address> <peer address>");
+            start_level = false;
+        }
+    }
 
-    let state = &mut MainState::new(ctx, &resource_dir);
+    let state = &mut MainState::new(ctx, &resource_dir, settings, config, start_level, championship, lockstep);
     if let Err(e) = event::run(ctx, ev, state) {
         println!("Error encountered: {}", e);
     } else {
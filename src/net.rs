@@ -0,0 +1,346 @@
+//! Two player lockstep over UDP.
+//!
+//! Each tick both machines send their player's input `Frame` for a few
+//! ticks in the future, and only simulate a tick once they have both
+//! players' frames for it.  The simulation is deterministic, so that is
+//! all they need to stay in sync.  A late frame stalls the game rather
+//! than being predicted and rolled back.
+//!
+//! Every level starts a new session from tick 0.  A machine leaving the
+//! level tells the other one, and one that hears nothing for a while
+//! gives up on its peer.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+use log::*;
+
+use crate::input::Frame;
+use crate::systems;
+
+/// Marks our packets, so stray ones get ignored.
+const MAGIC: &[u8; 4] = b"EZDG";
+/// How many ticks after it is read a player's input gets applied.  This
+/// gives it time to reach the other machine.
+const INPUT_DELAY: u32 = 3;
+/// Each packet carries this many of the latest frames, so a lost packet
+/// is made up for by the ones after it.
+const REDUNDANCY: u32 = 8;
+/// Stands in for the first tick number in a packet saying the sender has
+/// left the level.
+const QUIT: u32 = u32::MAX;
+/// How many ticks to wait for the peer before giving up on it.  This is
+/// long enough for the slower machine to finish loading the level.
+const STALL_TIMEOUT: u32 = 10 * systems::TICKS_PER_SECOND;
+
+pub struct Lockstep {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    /// Which player is at this machine, 0 or 1.
+    local_player: usize,
+    /// Counts the levels raced; packets from any other one are ignored.
+    session: u32,
+    /// The next tick to simulate.
+    tick: u32,
+    local: HashMap<u32, Frame>,
+    remote: HashMap<u32, Frame>,
+    /// How many ticks in a row we've been waiting for the peer.
+    stalled: u32,
+    /// The peer has said it left the level.
+    peer_left: bool,
+}
+
+impl Lockstep {
+    pub fn new(bind: SocketAddr, peer: SocketAddr, local_player: usize) -> io::Result<Self> {
+        let socket = UdpSocket::bind(bind)?;
+        socket.set_nonblocking(true)?;
+        info!("Racing {} from {} as player {}", peer, bind, local_player + 1);
+
+        let mut lockstep = Lockstep {
+            socket,
+            peer,
+            local_player,
+            session: 0,
+            tick: 0,
+            local: HashMap::new(),
+            remote: HashMap::new(),
+            stalled: 0,
+            peer_left: false,
+        };
+        lockstep.start_session();
+        Ok(lockstep)
+    }
+
+    /// Starts over from tick 0 for a new level.  Both machines do this
+    /// once per level, so their sessions stay in step.
+    pub fn reset(&mut self) {
+        self.session += 1;
+        self.start_session();
+    }
+
+    fn start_session(&mut self) {
+        self.tick = 0;
+        self.stalled = 0;
+        self.peer_left = false;
+        self.local.clear();
+        self.remote.clear();
+        // nobody has pressed anything during the first few ticks
+        for tick in 0..INPUT_DELAY {
+            self.local.insert(tick, Frame::default());
+            self.remote.insert(tick, Frame::default());
+        }
+    }
+
+    /// Whether the peer has left the level, or been silent for so long
+    /// that it has probably gone.
+    pub fn disconnected(&self) -> bool {
+        self.stalled >= STALL_TIMEOUT
+            || (self.peer_left && !self.remote.contains_key(&self.tick))
+    }
+
+    /// Tells the peer this machine has left the level.  If the packet
+    /// gets lost, the peer times out instead.
+    pub fn quit(&self) {
+        self.send_packet(QUIT, &[]);
+    }
+
+    /// Queues the local player's input and returns both players' input
+    /// for the next tick, in player order, or None if the peer's hasn't
+    /// arrived yet and the game has to wait for it.
+    pub fn advance(&mut self, local: Frame) -> Option<Vec<Frame>> {
+        self.receive();
+        let future = self.tick + INPUT_DELAY;
+        // store it the way the peer will see it, rounding and all
+        self.local.entry(future).or_insert_with(|| Frame::decode(&local.encode()));
+        self.send();
+
+        let remote = match self.remote.get(&self.tick) {
+            Some(frame) => *frame,
+            None => {
+                self.stalled += 1;
+                if self.stalled == STALL_TIMEOUT {
+                    warn!("Giving up on {} at tick {}", self.peer, self.tick);
+                } else if self.stalled % systems::TICKS_PER_SECOND == 0 {
+                    info!("Waiting for {} at tick {}", self.peer, self.tick);
+                }
+                return None;
+            }
+        };
+        let local = self.local[&self.tick];
+        self.stalled = 0;
+
+        // frames the peer can no longer be missing, and ones we've used
+        let oldest_sent = (future + 1).saturating_sub(REDUNDANCY);
+        self.local.retain(|&tick, _| tick >= oldest_sent);
+        let tick = self.tick;
+        self.remote.retain(|&t, _| t > tick);
+        self.tick += 1;
+
+        let mut frames = vec![Frame::default(); 2];
+        frames[self.local_player] = local;
+        frames[1 - self.local_player] = remote;
+        Some(frames)
+    }
+
+    /// Sends the latest local frames.
+    fn send(&self) {
+        let latest = self.tick + INPUT_DELAY;
+        let first = (latest + 1).saturating_sub(REDUNDANCY);
+        let frames: Vec<Frame> = (first..=latest).map_while(|tick| self.local.get(&tick).copied()).collect();
+        self.send_packet(first, &frames);
+    }
+
+    /// Sends little-endian session and tick numbers, then the frames
+    /// from that tick on in order.
+    fn send_packet(&self, first: u32, frames: &[Frame]) {
+        let mut packet = Vec::with_capacity(MAGIC.len() + 8 + frames.len() * Frame::SIZE);
+        packet.extend_from_slice(MAGIC);
+        packet.extend_from_slice(&self.session.to_le_bytes());
+        packet.extend_from_slice(&first.to_le_bytes());
+        for frame in frames {
+            packet.extend_from_slice(&frame.encode());
+        }
+        if let Err(e) = self.socket.send_to(&packet, self.peer) {
+            if e.kind() != io::ErrorKind::WouldBlock {
+                debug!("Couldn't send to {}: {}", self.peer, e);
+            }
+        }
+    }
+
+    fn receive(&mut self) {
+        let mut buffer = [0u8; 512];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((len, from)) if from == self.peer => self.read_packet(&buffer[..len]),
+                Ok((_, from)) => debug!("Ignoring a packet from {}", from),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                // e.g. the peer isn't listening yet
+                Err(e) => {
+                    debug!("Couldn't receive from {}: {}", self.peer, e);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn read_packet(&mut self, packet: &[u8]) {
+        if packet.len() < MAGIC.len() + 8 || &packet[..MAGIC.len()] != MAGIC {
+            return;
+        }
+        let number = |at: usize| {
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(&packet[at..at + 4]);
+            u32::from_le_bytes(bytes)
+        };
+        if number(MAGIC.len()) != self.session {
+            return;
+        }
+        let first = number(MAGIC.len() + 4);
+        if first == QUIT {
+            if !self.peer_left {
+                info!("{} left the race at tick {}", self.peer, self.tick);
+            }
+            self.peer_left = true;
+            return;
+        }
+        for (i, bytes) in packet[MAGIC.len() + 8..].chunks(Frame::SIZE).enumerate() {
+            let tick = first + i as u32;
+            if bytes.len() == Frame::SIZE && tick >= self.tick {
+                self.remote.entry(tick).or_insert_with(|| Frame::decode(bytes));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lockstep() -> Lockstep {
+        // nothing is listening at the peer; packets are fed in by hand
+        Lockstep::new("127.0.0.1:0".parse().unwrap(), "127.0.0.1:9".parse().unwrap(), 0).unwrap()
+    }
+
+    fn frame(throttle: f32) -> Frame {
+        Frame::decode(&Frame { throttle, ..Frame::default() }.encode())
+    }
+
+    fn packet(first: u32, frames: &[Frame]) -> Vec<u8> {
+        session_packet(0, first, frames)
+    }
+
+    fn session_packet(session: u32, first: u32, frames: &[Frame]) -> Vec<u8> {
+        let mut packet = MAGIC.to_vec();
+        packet.extend_from_slice(&session.to_le_bytes());
+        packet.extend_from_slice(&first.to_le_bytes());
+        for frame in frames {
+            packet.extend_from_slice(&frame.encode());
+        }
+        packet
+    }
+
+    #[test]
+    fn reads_frames_from_first_tick_on() {
+        let mut lockstep = lockstep();
+        lockstep.read_packet(&packet(5, &[frame(0.5), frame(1.0)]));
+        assert_eq!(lockstep.remote[&5], frame(0.5));
+        assert_eq!(lockstep.remote[&6], frame(1.0));
+    }
+
+    #[test]
+    fn keeps_the_first_copy_of_a_frame() {
+        let mut lockstep = lockstep();
+        lockstep.read_packet(&packet(7, &[frame(0.5), frame(0.5)]));
+        // an older packet arriving late, overlapping the one before
+        lockstep.read_packet(&packet(5, &[frame(0.1), frame(0.1), frame(-1.0)]));
+        assert_eq!(lockstep.remote[&5], frame(0.1));
+        assert_eq!(lockstep.remote[&7], frame(0.5));
+        assert_eq!(lockstep.remote[&8], frame(0.5));
+    }
+
+    #[test]
+    fn ignores_used_ticks_and_bad_packets() {
+        let mut lockstep = lockstep();
+        lockstep.tick = 10;
+        lockstep.remote.clear();
+        lockstep.read_packet(&packet(8, &[frame(1.0), frame(1.0), frame(1.0)]));
+        assert!(!lockstep.remote.contains_key(&8));
+        assert!(!lockstep.remote.contains_key(&9));
+        assert_eq!(lockstep.remote[&10], frame(1.0));
+
+        let mut stray = packet(11, &[frame(1.0)]);
+        stray[0] = b'X';
+        lockstep.read_packet(&stray);
+        lockstep.read_packet(&MAGIC[..]);
+        let mut truncated = packet(12, &[frame(1.0)]);
+        truncated.pop();
+        lockstep.read_packet(&truncated);
+        assert_eq!(lockstep.remote.len(), 1);
+    }
+
+    #[test]
+    fn waits_for_the_peer() {
+        let mut lockstep = lockstep();
+        // the first few ticks are free, nobody has pressed anything yet
+        for _ in 0..INPUT_DELAY {
+            assert!(lockstep.advance(frame(1.0)).is_some());
+        }
+        assert!(lockstep.advance(frame(1.0)).is_none());
+
+        lockstep.read_packet(&packet(INPUT_DELAY, &[frame(-1.0)]));
+        let frames = lockstep.advance(frame(1.0)).unwrap();
+        assert_eq!(frames, vec![frame(1.0), frame(-1.0)]);
+    }
+
+    #[test]
+    fn ignores_other_sessions() {
+        let mut lockstep = lockstep();
+        lockstep.reset();
+        lockstep.read_packet(&session_packet(0, 5, &[frame(1.0)]));
+        lockstep.read_packet(&session_packet(2, 6, &[frame(1.0)]));
+        assert!(!lockstep.remote.contains_key(&5));
+        assert!(!lockstep.remote.contains_key(&6));
+        lockstep.read_packet(&session_packet(1, 7, &[frame(1.0)]));
+        assert_eq!(lockstep.remote[&7], frame(1.0));
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut lockstep = lockstep();
+        for _ in 0..INPUT_DELAY {
+            lockstep.advance(frame(1.0));
+        }
+        lockstep.read_packet(&packet(INPUT_DELAY, &[frame(1.0)]));
+        lockstep.reset();
+        assert_eq!(lockstep.tick, 0);
+        assert_eq!(lockstep.remote.len(), INPUT_DELAY as usize);
+        assert!(lockstep.remote.values().all(|&f| f == Frame::default()));
+    }
+
+    #[test]
+    fn peer_leaving_disconnects_once_its_frames_run_out() {
+        let mut lockstep = lockstep();
+        lockstep.read_packet(&packet(QUIT, &[]));
+        assert!(!lockstep.disconnected());
+        for _ in 0..INPUT_DELAY {
+            assert!(lockstep.advance(frame(1.0)).is_some());
+        }
+        assert!(lockstep.advance(frame(1.0)).is_none());
+        assert!(lockstep.disconnected());
+    }
+
+    #[test]
+    fn gives_up_on_a_silent_peer() {
+        let mut lockstep = lockstep();
+        for _ in 0..INPUT_DELAY {
+            lockstep.advance(frame(1.0));
+        }
+        for _ in 0..STALL_TIMEOUT - 1 {
+            assert!(lockstep.advance(frame(1.0)).is_none());
+        }
+        assert!(!lockstep.disconnected());
+        lockstep.advance(frame(1.0));
+        assert!(lockstep.disconnected());
+    }
+}
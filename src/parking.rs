@@ -0,0 +1,36 @@
+//! Scoring for the parking challenge.
+
+/// Points a perfect park would get; time, bumps and shunting back and
+/// forth take points away.
+const MAX_SCORE: f32 = 1000.0;
+const POINTS_PER_SECOND: f32 = 10.0;
+const POINTS_PER_COLLISION: f32 = 100.0;
+const POINTS_PER_DIRECTION_CHANGE: f32 = 25.0;
+
+/// How the player is doing at parking.  Lives in the specs world as a
+/// resource and is updated by the `ParkingSystem`.
+#[derive(Clone, Debug, Default)]
+pub struct ParkingChallenge {
+    /// Seconds since the challenge started.
+    pub elapsed: f32,
+    pub collisions: u32,
+    /// How often the car went from forwards to reverse or back.
+    pub direction_changes: u32,
+    /// Seconds the car has been parked in the bay without moving.
+    pub held: f32,
+    pub finished: bool,
+    /// Which way the car was last moving, 1.0 or -1.0, or 0.0 before it
+    /// has moved at all.
+    pub last_direction: f32,
+    /// Whether the car was up against something last tick.
+    pub was_blocked: bool,
+}
+
+impl ParkingChallenge {
+    pub fn score(&self) -> u32 {
+        let penalty = self.elapsed * POINTS_PER_SECOND
+            + self.collisions as f32 * POINTS_PER_COLLISION
+            + self.direction_changes as f32 * POINTS_PER_DIRECTION_CHANGE;
+        (MAX_SCORE - penalty).max(0.0) as u32
+    }
+}
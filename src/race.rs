@@ -0,0 +1,206 @@
+//! Settings for a single race, chosen before the `LevelScene` is built.
+
+use crate::components::{AiProfile, Lap};
+
+/// Catch-up for AI drivers: cars behind the leading player speed up and
+/// cars ahead slow down, scaled by how far apart they are.
+#[derive(Clone, Debug)]
+pub struct RubberBand {
+    /// Gap along the racing line, in pixels, at which the effect is at
+    /// full strength.
+    pub full_effect_gap: f32,
+    /// Top speed added, as a fraction, when an AI car is far behind.
+    pub max_boost: f32,
+    /// Top speed removed, as a fraction, when an AI car is far ahead.
+    pub max_slowdown: f32,
+}
+
+impl Default for RubberBand {
+    fn default() -> Self {
+        RubberBand {
+            full_effect_gap: 1024.0,
+            max_boost: 0.15,
+            max_slowdown: 0.25,
+        }
+    }
+}
+
+impl RubberBand {
+    /// How much to scale an AI car's top speed by, given how far it is
+    /// behind the player (negative when it is ahead).
+    pub fn speed_factor(&self, gap: f32) -> f32 {
+        let strength = (gap / self.full_effect_gap).max(-1.0).min(1.0);
+        if strength > 0.0 {
+            1.0 + strength * self.max_boost
+        } else {
+            1.0 + strength * self.max_slowdown
+        }
+    }
+}
+
+/// What happens to a car that leaves the road, i.e. has all four wheels
+/// off the `Course` layer's tiles.
+#[derive(Clone, Debug)]
+pub struct TrackLimits {
+    /// Seconds a car may be off the road before it gets penalised.
+    pub grace: f32,
+    /// Seconds added to the car's race time for each second off the road
+    /// after the grace period.
+    pub time_penalty: f32,
+    /// Invalidate the lap, for best lap times, once a single excursion
+    /// lasts longer than this.
+    pub invalidate_after: Option<f32>,
+    /// Limit the car to this fraction of its top speed while it is off
+    /// the road.
+    pub speed_limit: Option<f32>,
+}
+
+impl Default for TrackLimits {
+    fn default() -> Self {
+        TrackLimits {
+            grace: 0.5,
+            time_penalty: 1.0,
+            invalidate_after: Some(1.0),
+            speed_limit: Some(0.6),
+        }
+    }
+}
+
+/// What the player has to do.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameMode {
+    Race,
+    /// Park in the map's first parking bay, see `parking::ParkingChallenge`.
+    Parking,
+    /// Drive a lap without breaking the map's rules, see `exam::Exam`.
+    Exam,
+}
+
+/// Lives in the specs world as a resource while the race runs.
+#[derive(Clone, Debug)]
+pub struct RaceConfig {
+    pub mode: GameMode,
+    /// The Tiled map to race on, relative to the resource directory.
+    pub map: String,
+    /// Laps to finish a race.  The grid is behind the start line, so
+    /// crossing it the first time starts lap 1.
+    pub laps: i32,
+    /// How many players share the keyboard, each with a part of the window.
+    pub players: usize,
+    /// When racing over the network, the player at this machine; only
+    /// they are shown.
+    pub local_player: Option<usize>,
+    /// One AI car is spawned for each profile.
+    pub opponents: Vec<AiProfile>,
+    pub rubber_band: Option<RubberBand>,
+    /// How many ambient traffic cars to keep around the players.
+    pub traffic: usize,
+    /// How many pedestrians to keep wandering across the road.
+    pub pedestrians: usize,
+    /// No track limits means cars may cut across anything.
+    pub track_limits: Option<TrackLimits>,
+}
+
+impl Default for RaceConfig {
+    fn default() -> Self {
+        RaceConfig {
+            mode: GameMode::Race,
+            map: String::from("map1.tmx"),
+            laps: 3,
+            players: 1,
+            local_player: None,
+            opponents: vec![AiProfile::easy(), AiProfile::medium(), AiProfile::hard()],
+            rubber_band: Some(RubberBand::default()),
+            traffic: 3,
+            pedestrians: 2,
+            track_limits: Some(TrackLimits::default()),
+        }
+    }
+}
+
+impl RaceConfig {
+    /// The parking challenge, alone on the map.
+    pub fn parking() -> Self {
+        RaceConfig {
+            mode: GameMode::Parking,
+            map: String::from("map1.tmx"),
+            laps: 3,
+            players: 1,
+            local_player: None,
+            opponents: Vec::new(),
+            rubber_band: None,
+            traffic: 0,
+            pedestrians: 0,
+            track_limits: None,
+        }
+    }
+
+    /// A race against the clock, with the track to oneself.
+    pub fn time_trial() -> Self {
+        RaceConfig {
+            mode: GameMode::Race,
+            map: String::from("map1.tmx"),
+            laps: 3,
+            players: 1,
+            local_player: None,
+            opponents: Vec::new(),
+            rubber_band: None,
+            traffic: 0,
+            pedestrians: 0,
+            track_limits: Some(TrackLimits::default()),
+        }
+    }
+
+    /// The driving test, with some traffic to give way to.
+    pub fn exam() -> Self {
+        RaceConfig {
+            mode: GameMode::Exam,
+            map: String::from("map1.tmx"),
+            laps: 3,
+            players: 1,
+            local_player: None,
+            opponents: Vec::new(),
+            rubber_band: None,
+            traffic: 2,
+            pedestrians: 3,
+            track_limits: None,
+        }
+    }
+}
+
+/// How one car did in a race.
+#[derive(Clone, Debug)]
+pub struct Finisher {
+    pub name: String,
+    pub player: bool,
+    /// Seconds to finish, not counting penalties, or None if the car
+    /// didn't finish.
+    pub time: Option<f32>,
+    /// Seconds added for track limits and for hitting pedestrians.
+    pub penalty: f32,
+    /// Each completed lap, in order.
+    pub laps: Vec<Lap>,
+}
+
+impl Finisher {
+    pub fn total_time(&self) -> Option<f32> {
+        self.time.map(|time| time + self.penalty)
+    }
+
+    /// The fastest lap that track limits didn't invalidate.
+    pub fn best_lap(&self) -> Option<f32> {
+        self.laps.iter().filter(|lap| lap.valid).map(|lap| lap.time).fold(None, |best, time| match best {
+            Some(best) if best <= time => Some(best),
+            _ => Some(time),
+        })
+    }
+}
+
+/// The outcome of a race, with the cars in finishing order.
+#[derive(Clone, Debug)]
+pub struct RaceResult {
+    pub map: String,
+    pub finishers: Vec<Finisher>,
+    /// Whether a player beat the best time on the map.
+    pub new_record: bool,
+}
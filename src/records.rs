@@ -0,0 +1,54 @@
+//! The best race time on each map and race length, kept between runs.
+
+use std::collections::BTreeMap;
+
+use log::*;
+use serde::{Deserialize, Serialize};
+
+use crate::race::RaceConfig;
+use crate::util::{read_toml, write_toml};
+
+/// Where the records are saved, in ggez's filesystem.
+const RECORDS_PATH: &str = "/records.toml";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Records {
+    /// Best total time in seconds, penalties included, by `key`.
+    pub times: BTreeMap<String, f32>,
+}
+
+/// Races only compare with ones over the same number of laps of the same
+/// map, e.g. "map1.tmx/3".
+fn key(config: &RaceConfig) -> String {
+    format!("{}/{}", config.map, config.laps)
+}
+
+impl Records {
+    /// Reads the saved records, or starts afresh if there aren't any.
+    pub fn load(ctx: &mut ggez::Context) -> Self {
+        read_toml(ctx, RECORDS_PATH).unwrap_or_default()
+    }
+
+    pub fn save(&self, ctx: &mut ggez::Context) {
+        if let Err(e) = write_toml(ctx, RECORDS_PATH, self) {
+            warn!("Couldn't save records: {}", e);
+        }
+    }
+
+    pub fn best(&self, config: &RaceConfig) -> Option<f32> {
+        self.times.get(&key(config)).cloned()
+    }
+
+    /// Records the time if it beats the best one so far, returning whether
+    /// it did.
+    pub fn submit(&mut self, config: &RaceConfig, time: f32) -> bool {
+        match self.best(config) {
+            Some(best) if best <= time => false,
+            _ => {
+                info!("New record on {} over {} laps: {:.2}s", config.map, config.laps, time);
+                self.times.insert(key(config), time);
+                true
+            }
+        }
+    }
+}
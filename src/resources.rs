@@ -3,9 +3,7 @@
 use std::path;
 
 use ggez::{self, graphics};
-use ggez_goodies::tilemap::tiled as tiled;
 use log::*;
-use warmy;
 
 use crate::types::Error;
 
@@ -64,7 +62,7 @@ impl warmy::Load<ggez::Context, Key> for Image {
         match key {
             Key::Path(path) => graphics::Image::new(ctx, path)
                 .map(|x| warmy::Loaded::from(Image(x)))
-                .map_err(|e| Error::GgezError(e)),
+                .map_err(Error::GgezError),
         }
     }
 }
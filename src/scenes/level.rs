@@ -1,4 +1,6 @@
 
+use std::collections::HashMap;
+
 use ggez;
 use ggez::graphics;
 use ggez_goodies::scene;
@@ -13,109 +15,601 @@ use warmy;
 use ggez::nalgebra as na;
 use ncollide2d as nc;
 
+use crate::audio::{Sound, SoundQueue};
+use crate::championship::Championship;
 use crate::components as c;
+use crate::damage::{DamageQueue, DamageSource};
+use crate::exam::{Exam, Violation};
 use crate::util;
 use crate::input;
+use crate::parking::ParkingChallenge;
+use crate::race::{Finisher, GameMode, RaceConfig, RaceResult};
+use crate::records::Records;
 use crate::resources;
 use crate::scenes;
+use crate::scenes::loading::LoadingScene;
+use crate::scenes::pause::{PauseChoice, PauseScene};
+use crate::scenes::Transition;
 use crate::systems::*;
-use crate::world::World;
+use crate::track::{Rule, Track};
+use crate::types::Point2;
+use crate::world::{self, World};
 
 // use euclid;
 
-const MIN_VELOCITY: f32 = -0.5;
-const MAX_VELOCITY: f32 = 2.0;
-const TAU: f32 = std::f32::consts::PI * 2.0;
+/// The sprite images every level uses, which the loading scene loads
+/// ahead of time.
+pub const CARS_IMAGE: &str = "/images/cars-spritesheet.png";
+pub const KIWI_IMAGE: &str = "/images/kiwi.png";
+pub const SPRITE_IMAGES: &[&str] = &[CARS_IMAGE, KIWI_IMAGE];
+
+/// Width of one car in the spritesheet, in texture coordinates.
+const CAR_SPRITE_WIDTH: f32 = 76.0 / 384.0;
+
+const CAR_HEALTH: f32 = 100.0;
+/// Damage dealt to both cars per unit of speed they hit each other at.
+const IMPACT_DAMAGE: f32 = 8.0;
+
+/// Half the window size; the window is 800 x 600.
+const VIEW_HALF_WIDTH: f32 = 400.0;
+const VIEW_HALF_HEIGHT: f32 = 300.0;
+
+/// How much of the steering the steering assist takes away at top speed.
+const STEERING_ASSIST: f32 = 0.5;
+
+/// Traffic further than this from every player gets despawned.
+const TRAFFIC_DESPAWN_DISTANCE: f32 = 1000.0;
+/// How far outside the view, and away from other cars, traffic spawns.
+const TRAFFIC_SPAWN_MARGIN: f32 = 48.0;
+const TRAFFIC_SPAWN_CLEARANCE: f32 = 128.0;
+const TRAFFIC_SPAWN_ATTEMPTS: usize = 16;
+
+/// How far either side of the racing line pedestrians cross the road
+/// from, away from marked crossings.
+const PEDESTRIAN_ROADSIDE: f32 = 160.0;
+/// How often pedestrians use a marked crossing rather than any old spot,
+/// when the map has crossings.
+const PEDESTRIAN_CROSSING_CHANCE: f32 = 0.7;
+/// Seconds added to the race time for hitting one.
+const PEDESTRIAN_PENALTY: f32 = 5.0;
+
+/// One of the players, and their part of the window if they are shown on
+/// this machine.
+struct PlayerSlot {
+    entity: specs::Entity,
+    viewport: Option<graphics::Rect>,
+    /// The view gets drawn here first when the window is split.
+    canvas: Option<graphics::Canvas>,
+    /// Whether reset was held down last tick, so holding it only resets once.
+    reset_held: bool,
+}
 
 pub struct LevelScene {
     done: bool,
+    /// Whether Menu was pressed, to open the pause menu.
+    pausing: bool,
+    /// The entities of this level are tagged with this.
+    scene: c::SceneId,
     car: warmy::Res<resources::Image>,
+    kiwi: warmy::Res<resources::Image>,
     map: Map,
-    player_entity: specs::Entity,
+    /// Size of the map in pixels.
+    map_size: (f32, f32),
+    players: Vec<PlayerSlot>,
+    /// Every car in the race and its name, for the results.
+    racers: Vec<(specs::Entity, String)>,
+    /// The championship this race is part of, if any.
+    championship: Option<Championship>,
+    shot_mesh: graphics::Mesh,
+    /// Outline of the parking bay, in the parking challenge.
+    bay_mesh: Option<graphics::Mesh>,
+    /// A unit square, scaled to each stop line, and a traffic light.
+    stop_line_mesh: graphics::Mesh,
+    /// The racing line, drawn when the assist is on.
+    racing_line_mesh: Option<graphics::Mesh>,
+    signal_mesh: graphics::Mesh,
     dispatcher: specs::Dispatcher<'static, 'static>,
 }
 
 impl LevelScene {
-    pub fn new(ctx: &mut ggez::Context, world: &mut World) -> Self {
-        let done = false;
-        let car = world
-            .resources
-            .get::<resources::Image>(&resources::Key::from_path("/images/cars-spritesheet.png"), ctx)
-            .unwrap();
+    /// Sets up a race on the map, which the loading scene has parsed,
+    /// with its tileset images by the path the map gives them.  Fails if
+    /// the loading scene hasn't loaded every image the level needs.
+    pub fn new(
+        ctx: &mut ggez::Context,
+        world: &mut World,
+        config: RaceConfig,
+        tiled_map: tiled::Map,
+        tilesets: &HashMap<String, graphics::Image>,
+    ) -> ggez::GameResult<Self> {
+        let car = Self::sprite(world, ctx, CARS_IMAGE)?;
+        let kiwi = Self::sprite(world, ctx, KIWI_IMAGE)?;
+        // the tilemap only draws maps with the one tileset image
+        match &tiled_map.tilesets[..] {
+            [tileset] if tileset.images.len() == 1 && tilesets.contains_key(&tileset.images[0].source) => (),
+            _ => {
+                let message = format!("{} needs a single tileset with a loaded image", config.map);
+                return Err(ggez::GameError::ResourceLoadError(message));
+            }
+        }
+        let mut load_image = |_: &mut ggez::Context, path: &str| tilesets[path].clone();
 
-        let mut load_image = |ctx: &mut ggez::Context, path: &str| -> graphics::Image {
-            let mut path_in_resources = String::from("/");
-            path_in_resources.push_str(path);
-            graphics::Image::new(ctx, path_in_resources).unwrap()
-        };
+        // everything spawned from here on belongs to this level
+        let scene = world.upcoming_scene();
+        world.scene = scene;
+        let done = false;
 
-        let tiled_map = tiled::parse_file(&std::path::Path::new("resources/map1.tmx")).unwrap();
+        let track = Track::from_tiled(&tiled_map);
+        let map_size = (
+            (tiled_map.width * tiled_map.tile_width) as f32,
+            (tiled_map.height * tiled_map.tile_height) as f32,
+        );
         let map = ggez_goodies::tilemap::Map::from_tiled(
             ctx,
             tiled_map,
             &mut load_image
         );
-        let half_height = 76.0 / 2.0;
-        let half_width = 76.0 / 4.0;
 
-        // shared collision properties
-        let car_shape = nc::shape::Cuboid::new(na::Vector2::new(half_width, half_height));
-        let car_collision_group = nc::pipeline::object::CollisionGroups::new();
-        let contacts_query = nc::pipeline::object::GeometricQueryType::Contacts(0.0, 0.0);
-
-        // player 1
-        let player_entity = world.specs_world.create_entity()
-            .with(c::Position { point: util::point2(100.0, 300.0), rotation: 0.0 })
-            .with(c::Motion { velocity: util::vec2(0.0, 0.0), acceleration: util::vec2(0.0, 0.0), is_blocked: false, orientation: 0.0})
-            .with(c::Sprite { clip: graphics::Rect { x: 0.0, y: 0.0, h: 1.0, w: (76.0 / 384.0)}, scale: graphics::mint::Vector2 { x: 0.5f32, y: 0.5f32 }})
-            .build();
+        // players, two abreast with player 1 in front on the left; over
+        // the network only the one at this machine is shown
+        let shown: Vec<usize> = match config.local_player {
+            Some(id) => vec![id],
+            None => (0..config.players).collect(),
+        };
+        let viewports = Self::viewports(shown.len());
+        let mut players = Vec::new();
+        let mut player_points = Vec::new();
+        let mut racers = Vec::new();
+        for id in 0..config.players {
+            let point = util::point2(224.0 + (id % 2) as f32 * 64.0, 300.0 + (id / 2) as f32 * 96.0);
+            let entity = Self::spawn_car(world, point, 0.0, id % 5);
+            world.specs_world.write_storage::<c::Player>().insert(entity, c::Player { id }).expect("couldn't insert Player");
+            world.specs_world.write_storage::<c::RaceProgress>().insert(entity, c::RaceProgress::default()).expect("couldn't insert RaceProgress");
+            world.specs_world.write_storage::<c::Respawn>().insert(entity, c::Respawn::new(point, 0.0)).expect("couldn't insert Respawn");
+            world.specs_world.write_storage::<c::OffTrack>().insert(entity, c::OffTrack::default()).expect("couldn't insert OffTrack");
+            Self::arm(world, entity, world::FIRST_SHOOTER_GROUP + id);
 
-        // other car
-        let car_entity = world.specs_world.create_entity()
-            .with(c::Position { point: util::point2(100.0, 100.0), rotation: 0.0 })
-            .with(c::Sprite { clip: graphics::Rect { x: (76.0 / 384.0), y: 0.0, h: 1.0, w: (76.0 / 384.0)}, scale: graphics::mint::Vector2 { x: 0.5f32, y: 0.5f32 }})
-            .build();
+            let viewport = shown.iter().position(|&shown_id| shown_id == id).map(|i| viewports[i]);
+            let canvas = match viewport {
+                Some(viewport) if shown.len() > 1 => {
+                    Some(graphics::Canvas::new(ctx, viewport.w as u16, viewport.h as u16, ggez::conf::NumSamples::One).unwrap())
+                }
+                _ => None,
+            };
+            players.push(PlayerSlot { entity, viewport, canvas, reset_held: false });
+            player_points.push(point);
+            racers.push((entity, format!("Player {}", id + 1)));
+        }
 
-        // collisions
-        //
-        {
-            let mut collide_world = world.specs_world.write_resource::<nc::world::CollisionWorld<f32, specs::Entity>>();
+        // AI opponents, lined up two abreast behind each other
+        for (i, profile) in config.opponents.iter().enumerate() {
+            let x = if i % 2 == 0 { 224.0 } else { 288.0 };
+            let y = 640.0 + (i / 2) as f32 * 96.0;
+            let car_entity = Self::spawn_car(world, util::point2(x, y), 0.0, 1 + i % 4);
+            world.specs_world.write_storage::<c::AiDriver>().insert(car_entity, c::AiDriver::new(profile.clone())).expect("couldn't insert AiDriver");
+            world.specs_world.write_storage::<c::RaceProgress>().insert(car_entity, c::RaceProgress::default()).expect("couldn't insert RaceProgress");
+            world.specs_world.write_storage::<c::Respawn>().insert(car_entity, c::Respawn::new(util::point2(x, y), 0.0)).expect("couldn't insert Respawn");
+            world.specs_world.write_storage::<c::OffTrack>().insert(car_entity, c::OffTrack::default()).expect("couldn't insert OffTrack");
+            racers.push((car_entity, format!("CPU {}", i + 1)));
+        }
 
-            let (player_collider_handle, _) = collide_world.add(
-                na::Isometry2::new(na::Vector2::new(100.0, 300.0), na::zero()),
-                nc::shape::ShapeHandle::new(car_shape.clone()),
-                car_collision_group,
-                contacts_query,
-                player_entity,
-            );
-            world.specs_world.write_storage::<c::Collider>().insert(player_entity, c::Collider { handle: player_collider_handle }).expect("couldn't insert Collider");
+        let racing_line_mesh = if track.racing_line.samples().len() >= 2 {
+            let mut points: Vec<na::Point2<f32>> =
+                track.racing_line.samples().iter().map(|p| na::Point2::new(p.x, p.y)).collect();
+            if track.racing_line.closed {
+                points.push(points[0]);
+            }
+            graphics::Mesh::new_polyline(ctx, graphics::DrawMode::stroke(4.0), &points, graphics::Color::new(0.2, 0.8, 1.0, 0.5)).ok()
+        } else {
+            None
+        };
 
-            let (car_collider_handle, _) = collide_world.add(
-                na::Isometry2::new(na::Vector2::new(100.0, 100.0), na::zero()),
-                nc::shape::ShapeHandle::new(car_shape.clone()),
-                car_collision_group,
-                contacts_query,
-                car_entity,
-            );
-            world.specs_world.write_storage::<c::Collider>().insert(car_entity, c::Collider { handle: car_collider_handle }).expect("couldn't insert Collider");
+        let bay_mesh = match config.mode {
+            GameMode::Parking => track.parking_bays.first().map(|bay| {
+                let (hw, hh) = (bay.area.half_width, bay.area.half_height);
+                graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::stroke(3.0),
+                    graphics::Rect::new(-hw, -hh, hw * 2.0, hh * 2.0),
+                    graphics::Color::from_rgb(255, 255, 255),
+                ).unwrap()
+            }),
+            _ => None,
+        };
+        if config.mode == GameMode::Parking && bay_mesh.is_none() {
+            warn!("Parking challenge on a map without a parking bay");
         }
 
+        for signal in &track.signals {
+            let entity = world.specs_world.create_entity().with(signal.clone()).build();
+            world.tag(entity);
+        }
+
+        let traffic = config.traffic;
+        world.add_scene_resource(track);
+        world.add_scene_resource(config);
+        world.add_scene_resource(util::Rng::default());
+        world.add_scene_resource(SoundQueue::default());
+        world.add_scene_resource(DamageQueue::default());
+        world.add_scene_resource(ParkingChallenge::default());
+        world.add_scene_resource(Exam::default());
+
+        for _ in 0..traffic {
+            Self::spawn_traffic(world, &player_points);
+        }
+        Self::maintain_pedestrians(world);
 
         let mut dispatcher = Self::register_systems();
         dispatcher.setup(&mut world.specs_world.res);
 
-        LevelScene {
+        let shot_mesh = graphics::Mesh::new_circle(
+            ctx,
+            graphics::DrawMode::fill(),
+            na::Point2::new(0.0, 0.0),
+            4.0,
+            0.5,
+            graphics::Color::from_rgb(255, 220, 64),
+        ).unwrap();
+
+        let white = graphics::Color::from_rgb(255, 255, 255);
+        let stop_line_mesh = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(-0.5, -0.5, 1.0, 1.0),
+            white,
+        ).unwrap();
+        let signal_mesh = graphics::Mesh::new_circle(
+            ctx,
+            graphics::DrawMode::fill(),
+            na::Point2::new(0.0, 0.0),
+            10.0,
+            0.5,
+            white,
+        ).unwrap();
+
+        Ok(LevelScene {
             done,
+            pausing: false,
+            scene,
             car,
+            kiwi,
             map,
-            player_entity,
+            map_size,
+            players,
+            racers,
+            championship: None,
+            shot_mesh,
+            bay_mesh,
+            stop_line_mesh,
+            racing_line_mesh,
+            signal_mesh,
             dispatcher,
+        })
+    }
+
+    /// A sprite image, from the store where the loading scene put it.
+    fn sprite(world: &mut World, ctx: &mut ggez::Context, path: &str) -> ggez::GameResult<warmy::Res<resources::Image>> {
+        world
+            .resources
+            .get::<resources::Image>(&resources::Key::from_path(path), ctx)
+            .map_err(|e| ggez::GameError::ResourceLoadError(format!("{}: {:?}", path, e)))
+    }
+
+    /// Makes the race a round of a championship.
+    pub fn with_championship(mut self, championship: Championship) -> Self {
+        self.championship = Some(championship);
+        self
+    }
+
+    /// Splits the window between the players: side by side for two, in
+    /// quadrants for three or four.
+    fn viewports(players: usize) -> Vec<graphics::Rect> {
+        let (width, height) = (VIEW_HALF_WIDTH * 2.0, VIEW_HALF_HEIGHT * 2.0);
+        match players {
+            0 | 1 => vec![graphics::Rect::new(0.0, 0.0, width, height)],
+            2 => vec![
+                graphics::Rect::new(0.0, 0.0, VIEW_HALF_WIDTH, height),
+                graphics::Rect::new(VIEW_HALF_WIDTH, 0.0, VIEW_HALF_WIDTH, height),
+            ],
+            _ => (0..players.min(4))
+                .map(|i| graphics::Rect::new(
+                    (i % 2) as f32 * VIEW_HALF_WIDTH,
+                    (i / 2) as f32 * VIEW_HALF_HEIGHT,
+                    VIEW_HALF_WIDTH,
+                    VIEW_HALF_HEIGHT,
+                ))
+                .collect(),
+        }
+    }
+
+    /// Creates a car entity and its collision object.  `design` picks
+    /// one of the cars in the spritesheet.
+    fn spawn_car(world: &mut World, point: Point2, orientation: f32, design: usize) -> specs::Entity {
+        let half_height = 76.0 / 2.0;
+        let half_width = 76.0 / 4.0;
+        let car_shape = nc::shape::Cuboid::new(na::Vector2::new(half_width, half_height));
+
+        let entity = world.specs_world.create_entity()
+            .with(c::Position { point, rotation: orientation })
+            .with(c::Motion { velocity: util::vec2(0.0, 0.0), acceleration: util::vec2(0.0, 0.0), is_blocked: false, orientation })
+            .with(c::Controls::default())
+            .with(c::Handling::default())
+            .with(c::Health::new(CAR_HEALTH))
+            .with(c::Sprite { image: c::SpriteImage::Cars, clip: graphics::Rect { x: design as f32 * CAR_SPRITE_WIDTH, y: 0.0, h: 1.0, w: CAR_SPRITE_WIDTH }, scale: graphics::mint::Vector2 { x: 0.5f32, y: 0.5f32 }})
+            .build();
+        world.tag(entity);
+
+        let mut collide_world = world.specs_world.write_resource::<nc::world::CollisionWorld<f32, specs::Entity>>();
+        let (handle, _) = collide_world.add(
+            na::Isometry2::new(na::Vector2::new(point.x, point.y), orientation),
+            nc::shape::ShapeHandle::new(car_shape),
+            nc::pipeline::object::CollisionGroups::new().with_membership(&[world::CAR_GROUP]),
+            nc::pipeline::object::GeometricQueryType::Contacts(0.0, 0.0),
+            entity,
+        );
+        world.specs_world.write_storage::<c::Collider>().insert(entity, c::Collider { handle }).expect("couldn't insert Collider");
+        entity
+    }
+
+    /// Gives a car a `Weapon`, and puts it in the weapon's collision group
+    /// so its own shots pass through it.
+    fn arm(world: &mut World, entity: specs::Entity, group: usize) {
+        let collider = world.specs_world.read_storage::<c::Collider>().get(entity).cloned().expect("Armed car w/o collider?");
+        let groups = nc::pipeline::object::CollisionGroups::new().with_membership(&[world::CAR_GROUP, group]);
+        if let Some(object) = world.specs_world.write_resource::<nc::world::CollisionWorld<f32, specs::Entity>>().get_mut(collider.handle) {
+            object.set_collision_groups(groups);
+        }
+        world.specs_world.write_storage::<c::Weapon>().insert(entity, c::Weapon::new(group)).expect("couldn't insert Weapon");
+    }
+
+    /// Spawns a traffic car at a random spot along a traffic lane that no
+    /// player can see.  Returns false if no free spot turned up.
+    fn spawn_traffic(world: &mut World, players: &[Point2]) -> bool {
+        let lane_count = world.specs_world.read_resource::<Track>().traffic_lanes.len();
+        if lane_count == 0 {
+            return false;
+        }
+
+        for _ in 0..TRAFFIC_SPAWN_ATTEMPTS {
+            let (lane, point, orientation, design) = {
+                let track = world.specs_world.read_resource::<Track>();
+                let mut rng = world.specs_world.write_resource::<util::Rng>();
+                let lane = rng.index(lane_count);
+                let spline = &track.traffic_lanes[lane];
+                let distance = rng.range(0.0, spline.length());
+                (lane, spline.sample(distance), util::orientation_of(spline.tangent(distance)), 2 + rng.index(3))
+            };
+
+            let off_screen = players.iter().all(|p| {
+                (point.x - p.x).abs() > VIEW_HALF_WIDTH + TRAFFIC_SPAWN_MARGIN
+                    || (point.y - p.y).abs() > VIEW_HALF_HEIGHT + TRAFFIC_SPAWN_MARGIN
+            });
+            let in_range = players.iter().any(|p| (point - *p).length() < TRAFFIC_DESPAWN_DISTANCE);
+            let free = {
+                let positions = world.specs_world.read_storage::<c::Position>();
+                let motions = world.specs_world.read_storage::<c::Motion>();
+                (&positions, &motions).join().all(|(p, _)| (point - p.point).length() > TRAFFIC_SPAWN_CLEARANCE)
+            };
+            if off_screen && in_range && free {
+                let entity = Self::spawn_car(world, point, orientation, design);
+                world.specs_world.write_storage::<c::Traffic>().insert(entity, c::Traffic::new(lane)).expect("couldn't insert Traffic");
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Despawns traffic that has been left far behind and spawns new cars
+    /// out of sight, so there is always the same amount around.
+    fn maintain_traffic(&mut self, world: &mut World) {
+        let players: Vec<Point2> = {
+            let players = world.specs_world.read_storage::<c::Player>();
+            let positions = world.specs_world.read_storage::<c::Position>();
+            (&players, &positions).join().map(|(_, p)| p.point).collect()
+        };
+        let far: Vec<specs::Entity> = {
+            let entities = world.specs_world.entities();
+            let traffic = world.specs_world.read_storage::<c::Traffic>();
+            let positions = world.specs_world.read_storage::<c::Position>();
+            (&entities, &traffic, &positions).join()
+                .filter(|(_, _, pos)| players.iter().all(|p| (pos.point - *p).length() > TRAFFIC_DESPAWN_DISTANCE))
+                .map(|(e, _, _)| e)
+                .collect()
+        };
+        for entity in far {
+            world.despawn(entity);
+        }
+
+        let wanted = world.specs_world.read_resource::<RaceConfig>().traffic;
+        let count = world.specs_world.read_storage::<c::Traffic>().join().count();
+        for _ in count..wanted {
+            if !Self::spawn_traffic(world, &players) {
+                break;
+            }
+        }
+    }
+
+    /// Turns shots that hit something into damage, and despawns them along
+    /// with any that flew too far.
+    fn update_shots(&mut self, world: &mut World) {
+        let mut spent = Vec::new();
+        {
+            let collide_world = world.specs_world.read_resource::<nc::world::CollisionWorld<f32, specs::Entity>>();
+            let shots = world.specs_world.read_storage::<c::Shot>();
+            let mut damage = world.specs_world.write_resource::<DamageQueue>();
+            for event in collide_world.proximity_events() {
+                if event.new_status != nc::query::Proximity::Intersecting {
+                    continue;
+                }
+                let (obj1, obj2) = match (collide_world.collision_object(event.collider1), collide_world.collision_object(event.collider2)) {
+                    (Some(obj1), Some(obj2)) => (obj1, obj2),
+                    _ => continue,
+                };
+                for &(shot_entity, target) in &[(*obj1.data(), *obj2.data()), (*obj2.data(), *obj1.data())] {
+                    if let Some(shot) = shots.get(shot_entity) {
+                        if shots.get(target).is_none() && !spent.contains(&shot_entity) {
+                            damage.push(target, shot.damage as f32, DamageSource::Shot);
+                            spent.push(shot_entity);
+                        }
+                    }
+                }
+            }
+
+            let entities = world.specs_world.entities();
+            let lifetimes = world.specs_world.read_storage::<c::Lifetime>();
+            for (entity, lifetime) in (&entities, &lifetimes).join() {
+                if lifetime.remaining <= 0.0 && !spent.contains(&entity) {
+                    spent.push(entity);
+                }
+            }
+        }
+        for entity in spent {
+            world.despawn(entity);
+        }
+    }
+
+    /// Picks a way across the road for a pedestrian: a marked crossing,
+    /// or somewhere along the racing line.
+    fn pedestrian_path(world: &mut World) -> Option<(Point2, Point2)> {
+        let track = world.specs_world.read_resource::<Track>();
+        let mut rng = world.specs_world.write_resource::<util::Rng>();
+        let line = &track.racing_line;
+        let (a, b) = if !track.crossings.is_empty() && (line.is_empty() || rng.next_f32() < PEDESTRIAN_CROSSING_CHANCE) {
+            track.crossings[rng.index(track.crossings.len())]
+        } else if !line.is_empty() {
+            let distance = rng.range(0.0, line.length());
+            let tangent = line.tangent(distance);
+            let across = util::vec2(-tangent.y, tangent.x) * PEDESTRIAN_ROADSIDE;
+            let point = line.sample(distance);
+            (point + across, point - across)
+        } else {
+            return None;
+        };
+        if rng.next_f32() < 0.5 {
+            Some((a, b))
+        } else {
+            Some((b, a))
+        }
+    }
+
+    /// Creates a kiwi at the start of a path across the road.  It is only
+    /// a sensor, so cars run into it rather than bounce off it.
+    fn spawn_pedestrian(world: &mut World, from: Point2, to: Point2) {
+        let wait = world.specs_world.write_resource::<util::Rng>().range(0.0, 3.0);
+        let entity = world.specs_world.create_entity()
+            .with(c::Position { point: from, rotation: 0.0 })
+            .with(c::Pedestrian::new(from, to, wait))
+            .with(c::Sprite { image: c::SpriteImage::Kiwi, clip: graphics::Rect::new(0.0, 0.0, 1.0, 1.0), scale: graphics::mint::Vector2 { x: 0.5f32, y: 0.5f32 }})
+            .build();
+        world.tag(entity);
+
+        let mut collide_world = world.specs_world.write_resource::<nc::world::CollisionWorld<f32, specs::Entity>>();
+        let (handle, _) = collide_world.add(
+            na::Isometry2::new(na::Vector2::new(from.x, from.y), 0.0),
+            nc::shape::ShapeHandle::new(nc::shape::Ball::new(14.0)),
+            nc::pipeline::object::CollisionGroups::new()
+                .with_membership(&[world::PEDESTRIAN_GROUP])
+                .with_blacklist(&[world::PEDESTRIAN_GROUP]),
+            nc::pipeline::object::GeometricQueryType::Proximity(0.0),
+            entity,
+        );
+        world.specs_world.write_storage::<c::Collider>().insert(entity, c::Collider { handle }).expect("couldn't insert Collider");
+    }
+
+    /// Tops pedestrians back up to the number the race wants.
+    fn maintain_pedestrians(world: &mut World) {
+        let wanted = world.specs_world.read_resource::<RaceConfig>().pedestrians;
+        let count = world.specs_world.read_storage::<c::Pedestrian>().join().count();
+        for _ in count..wanted {
+            match Self::pedestrian_path(world) {
+                Some((from, to)) => Self::spawn_pedestrian(world, from, to),
+                None => break,
+            }
+        }
+    }
+
+    /// Deals with cars running into pedestrians: the pedestrian is gone,
+    /// and a player who hit it is penalised the way the game mode says.
+    fn update_pedestrians(&mut self, world: &mut World) {
+        let mut hits = Vec::new();
+        {
+            let collide_world = world.specs_world.read_resource::<nc::world::CollisionWorld<f32, specs::Entity>>();
+            let pedestrians = world.specs_world.read_storage::<c::Pedestrian>();
+            let controls = world.specs_world.read_storage::<c::Controls>();
+            for event in collide_world.proximity_events() {
+                if event.new_status != nc::query::Proximity::Intersecting {
+                    continue;
+                }
+                let (obj1, obj2) = match (collide_world.collision_object(event.collider1), collide_world.collision_object(event.collider2)) {
+                    (Some(obj1), Some(obj2)) => (obj1, obj2),
+                    _ => continue,
+                };
+                for &(pedestrian, car) in &[(*obj1.data(), *obj2.data()), (*obj2.data(), *obj1.data())] {
+                    if pedestrians.get(pedestrian).is_some() && controls.get(car).is_some() && !hits.iter().any(|&(p, _)| p == pedestrian) {
+                        hits.push((pedestrian, car));
+                    }
+                }
+            }
+        }
+
+        for (pedestrian, car) in hits {
+            let point = world.specs_world.read_storage::<c::Position>().get(pedestrian).map(|p| p.point);
+            world.despawn(pedestrian);
+            let point = match point {
+                Some(point) => point,
+                None => continue,
+            };
+            world.specs_world.write_resource::<SoundQueue>().play(Sound::Squawk, point);
+            if world.specs_world.read_storage::<c::Player>().get(car).is_none() {
+                continue;
+            }
+            info!("{:?} hit a pedestrian at {:?}", car, point);
+            let mode = world.specs_world.read_resource::<RaceConfig>().mode;
+            match mode {
+                GameMode::Exam => {
+                    let mut exam = world.specs_world.write_resource::<Exam>();
+                    let time = exam.elapsed;
+                    exam.violations.push(Violation { time, position: point, rule: Rule::HitPedestrian });
+                }
+                GameMode::Parking => world.specs_world.write_resource::<ParkingChallenge>().collisions += 1,
+                GameMode::Race => {
+                    if let Some(progress) = world.specs_world.write_storage::<c::RaceProgress>().get_mut(car) {
+                        progress.penalty += PEDESTRIAN_PENALTY;
+                    }
+                }
+            }
+        }
+        Self::maintain_pedestrians(world);
+    }
+
+    /// There is no sound output yet, so for now sound events just get logged.
+    fn play_sounds(&mut self, world: &mut World) {
+        let volume = world.settings.effects_volume();
+        let mut sounds = world.specs_world.write_resource::<SoundQueue>();
+        for event in sounds.events.drain(..) {
+            if volume > 0.0 {
+                info!("Sound: {:?} at {:?}, volume {:.1}", event.sound, event.position, volume);
+            }
         }
     }
 
     fn register_systems() -> specs::Dispatcher<'static, 'static> {
         let builder = specs::DispatcherBuilder::new()
-            .with(MovementSystem, "sys_movement", &[])
+            .with(TrafficSignalSystem, "sys_signals", &[])
+            .with(RaceProgressSystem, "sys_race_progress", &[])
+            .with(AiDriverSystem, "sys_ai_driver", &["sys_race_progress", "sys_signals"])
+            .with(TrafficSystem, "sys_traffic", &["sys_signals"])
+            .with(PedestrianSystem, "sys_pedestrians", &[])
+            .with(TrackLimitsSystem, "sys_track_limits", &["sys_race_progress"])
+            .with(VehicleSystem, "sys_vehicle", &["sys_ai_driver", "sys_traffic", "sys_respawn", "sys_track_limits"])
+            .with(WeaponSystem, "sys_weapon", &["sys_vehicle"])
+            .with(LifetimeSystem, "sys_lifetime", &["sys_weapon"])
+            .with(DamageSystem, "sys_damage", &[])
+            .with(WreckSystem, "sys_wreck", &["sys_damage"])
+            .with(RespawnSystem, "sys_respawn", &["sys_wreck"])
+            .with(MovementSystem, "sys_movement", &["sys_weapon"])
+            .with(ParkingSystem, "sys_parking", &["sys_movement"])
+            .with(ExamSystem, "sys_exam", &["sys_movement", "sys_race_progress", "sys_signals"])
             .with(CollisionSystem, "sys_collision", &[]);
         // builder.add_thread_local(RenderSystem);
         builder.build()
@@ -126,7 +620,7 @@ impl LevelScene {
         let mut collide_world = world.specs_world.write_resource::<nc::world::CollisionWorld<f32, specs::Entity>>();
         collide_world.update();
         let mut motions = world.specs_world.write_storage::<c::Motion>();
-
+        let mut damage = world.specs_world.write_resource::<DamageQueue>();
 
         // gameworld.collide_world.update();
         for e in collide_world.contact_events() {
@@ -134,7 +628,17 @@ impl LevelScene {
             match e {
                 ncollide2d::pipeline::narrow_phase::ContactEvent::Started(handle1, handle2) =>
                     {
-                        println!("contact started!");
+                        debug!("contact started!");
+
+                        // the harder the hit, the more it hurts
+                        {
+                            let e1 = *collide_world.collision_object(*handle1).expect("missing coll obj1").data();
+                            let e2 = *collide_world.collision_object(*handle2).expect("missin coll obj2").data();
+                            let velocity = |e| motions.get(e).map_or(util::vec2(0.0, 0.0), |m: &c::Motion| m.velocity);
+                            let impact = (velocity(e1) - velocity(e2)).length();
+                            damage.push(e1, impact * IMPACT_DAMAGE, DamageSource::Impact);
+                            damage.push(e2, impact * IMPACT_DAMAGE, DamageSource::Impact);
+                        }
 
                         // look up collision object
                         let obj1 = collide_world.collision_object(*handle1).expect("missing coll obj1");
@@ -142,7 +646,7 @@ impl LevelScene {
                         let entity1: &specs::Entity = obj1.data();
                         if let Some(motion) = motions.get_mut(*entity1) {
                             motion.is_blocked = true;
-                            motion.acceleration.y = motion.acceleration.y * -1.0;
+                            motion.acceleration.y *= -1.0;
                             motion.update();
                         }
 
@@ -151,14 +655,14 @@ impl LevelScene {
                         if let Some(motion) = motions.get_mut(*entity2) {
                             motion.is_blocked = true;
                             // want to use reflect here.
-                            motion.acceleration.y = motion.acceleration.y * -1.0;
+                            motion.acceleration.y *= -1.0;
                             motion.update();
                             // motion.velocity = rotation.transform_vector(&player_motion.acceleration);
                         }
                     }
                 ncollide2d::pipeline::narrow_phase::ContactEvent::Stopped(handle1, handle2) =>
                 {
-                    println!("contact ended");
+                    debug!("contact ended");
                     let obj1 = collide_world.collision_object(*handle1).expect("missing coll obj1");
                     // look up entity
                     let entity1: &specs::Entity = obj1.data();
@@ -176,71 +680,332 @@ impl LevelScene {
             }
         }
     }
-}
 
-//  fn camera_draw(ctx: &mut ggez::Context, drawable: &graphics::Drawable, params: graphics::DrawParam) -> ggez::GameResult<()> {
-//     Ok(())
-// }
+    /// Draws the level as one player sees it, into their part of the
+    /// window, with their HUD on top.
+    fn draw_view(&mut self, gameworld: &mut World, ctx: &mut ggez::Context, view: usize) -> ggez::GameResult<()> {
+        let pos = gameworld.specs_world.read_storage::<c::Position>();
+        let player_entity = self.players[view].entity;
+        let viewport = self.players[view].viewport.expect("Drawing a player who isn't shown?");
 
-impl scene::Scene<World, input::Event> for LevelScene {
-    fn update(&mut self, gameworld: &mut World, _ctx: &mut ggez::Context) -> scenes::Switch {
-        self.dispatcher.dispatch(&mut gameworld.specs_world.res);
+        // keep the player in the middle of the view, but don't go past the
+        // edges of the map
+        let player_point = pos.get(player_entity).unwrap().point;
+        let camera_offset = na::Point2::new(
+            (viewport.w / 2.0 - player_point.x).max(viewport.w - self.map_size.0).min(0.0),
+            (viewport.h / 2.0 - player_point.y).max(viewport.h - self.map_size.1).min(0.0),
+        );
+        // map
+        graphics::draw(ctx, &self.map, graphics::DrawParam::default().dest(camera_offset)).unwrap();
+        // camera_draw(ctx, &self.map, graphics::DrawParam::default()).unwrap();
 
-        self.update_collisions(gameworld);
-        if self.done {
-            scene::SceneSwitch::Pop
-        } else {
-            scene::SceneSwitch::None
+        if gameworld.settings.assists.racing_line {
+            if let Some(ref mesh) = self.racing_line_mesh {
+                graphics::draw(ctx, mesh, graphics::DrawParam::default().dest(camera_offset))?;
+            }
         }
-    }
 
-    fn draw(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
-        let pos = gameworld.specs_world.read_storage::<c::Position>();
-
-        let mut camera_offset = na::Point2::new(0.0, 0.0);
+        // traffic lights, beside the road to the right of their stop lines
+        let tags = gameworld.specs_world.read_storage::<c::SceneTag>();
+        let scene = self.scene;
+        let ours = |tag: &c::SceneTag| tag.scene == scene;
+        let signals = gameworld.specs_world.read_storage::<c::TrafficSignal>();
+        for (signal, _) in (&signals, &tags).join().filter(|(_, t)| ours(t)) {
+            let line = &signal.stop_line;
+            let color = match signal.state() {
+                c::SignalState::Green => graphics::Color::new(0.2, 0.9, 0.2, 1.0),
+                c::SignalState::Yellow => graphics::Color::new(1.0, 0.8, 0.1, 1.0),
+                c::SignalState::Red => graphics::Color::new(0.9, 0.1, 0.1, 1.0),
+            };
+            let center = na::Point2::new(camera_offset.x + line.position.x, camera_offset.y + line.position.y);
+            let params = graphics::DrawParam::default()
+                .dest(center)
+                .rotation(line.rotation)
+                .scale(na::Vector2::new(line.half_width * 2.0, line.half_height * 2.0))
+                .color(graphics::Color::new(1.0, 1.0, 1.0, 0.4));
+            graphics::draw(ctx, &self.stop_line_mesh, params)?;
 
-        // todo: update this elsewhere
-        let player_point = pos.get(self.player_entity).unwrap().point;
-        // window is 800 x 600
-        if player_point.x > 400.0 {
-            if player_point.x < (self.map.width as f32 - 400.0) {
-                camera_offset.x = 400.0 - player_point.x;
-            } else {
-                camera_offset.x = self.map.width as f32 + 400.0;
-            }
+            let side = util::heading(signal.direction + std::f32::consts::FRAC_PI_2) * (line.half_width.max(line.half_height) + 16.0);
+            let params = graphics::DrawParam::default()
+                .dest(na::Point2::new(center.x + side.x, center.y + side.y))
+                .color(color);
+            graphics::draw(ctx, &self.signal_mesh, params)?;
         }
-        if player_point.y > 300.0 {
-            camera_offset.y = 300.0 - player_point.y;
+
+        if let Some(ref bay_mesh) = self.bay_mesh {
+            let track = gameworld.specs_world.read_resource::<Track>();
+            let bay = &track.parking_bays[0].area;
+            let params = graphics::DrawParam::default()
+                .dest(na::Point2::new(camera_offset.x + bay.position.x, camera_offset.y + bay.position.y))
+                .rotation(bay.rotation);
+            graphics::draw(ctx, bay_mesh, params)?;
         }
-        // map
-        graphics::draw(ctx, &self.map, graphics::DrawParam::default().dest(camera_offset)).unwrap();
-        // camera_draw(ctx, &self.map, graphics::DrawParam::default()).unwrap();
 
         // sprites
         let sprite = gameworld.specs_world.read_storage::<c::Sprite>();
         let offset_x: f32 = 0.5;
         let offset_y: f32 = 0.5;
-        for (p, s) in (&pos, &sprite).join() {
+        let wrecked = gameworld.specs_world.read_storage::<c::Wrecked>();
+        let invulnerable = gameworld.specs_world.read_storage::<c::Invulnerable>();
+        for (p, s, w, inv, _) in (&pos, &sprite, wrecked.maybe(), invulnerable.maybe(), &tags).join().filter(|j| ours(j.4)) {
             let mut params = graphics::DrawParam::default();
+            if w.is_some() {
+                params.color = graphics::Color::new(0.3, 0.3, 0.3, 1.0);
+            } else if inv.is_some() {
+                params.color = graphics::Color::new(1.0, 1.0, 1.0, 0.5);
+            }
             params.src = s.clip;
             params.rotation = p.rotation;
             params.scale = s.scale;
             params.offset = na::Point2::new(offset_x, offset_y).into();
             params.dest = na::Point2::new(camera_offset.x + p.point.x, camera_offset.y + p.point.y).into();
+            let image = match s.image {
+                c::SpriteImage::Cars => &self.car,
+                c::SpriteImage::Kiwi => &self.kiwi,
+            };
             graphics::draw(
                 ctx,
-                &(self.car.borrow().0),
+                &(image.borrow().0),
                 params,
             )?;
         }
 
+        // shots
+        let shots = gameworld.specs_world.read_storage::<c::Shot>();
+        for (p, _, _) in (&pos, &shots, &tags).join().filter(|j| ours(j.2)) {
+            let dest = na::Point2::new(camera_offset.x + p.point.x, camera_offset.y + p.point.y);
+            graphics::draw(ctx, &self.shot_mesh, graphics::DrawParam::default().dest(dest))?;
+        }
+
         // ui
         let motions = gameworld.specs_world.read_storage::<c::Motion>();
-        let pm = motions.get(self.player_entity).expect("Player w/o motion?");
-        let text = graphics::Text::new(format!("o = {}, v = {}, x = {}, y = {}", pm.orientation, pm.acceleration.y, pm.velocity.x, pm.velocity.y));
+        let pm = motions.get(player_entity).expect("Player w/o motion?");
+        let healths = gameworld.specs_world.read_storage::<c::Health>();
+        let hp = healths.get(player_entity).map_or(0.0, |h| h.current);
+        let text = graphics::Text::new(format!("o = {}, v = {}, x = {}, y = {}, hp = {}", pm.orientation, pm.acceleration.y, pm.velocity.x, pm.velocity.y, hp));
 
         graphics::draw(ctx, &text, graphics::DrawParam::default().dest(na::Point2::new(0.0, 0.0))).unwrap();
 
+        let progresses = gameworld.specs_world.read_storage::<c::RaceProgress>();
+        if progresses.get(player_entity).map_or(false, |p| p.wrong_way) {
+            let mut warning = graphics::Text::new("WRONG WAY");
+            warning.set_font(graphics::Font::default(), graphics::Scale::uniform(48.0));
+            let x = viewport.w / 2.0 - warning.width(ctx) as f32 / 2.0;
+            let params = graphics::DrawParam::default()
+                .dest(na::Point2::new(x, 80.0))
+                .color(graphics::Color::new(1.0, 0.2, 0.2, 1.0));
+            graphics::draw(ctx, &warning, params)?;
+        }
+
+        let off_tracks = gameworld.specs_world.read_storage::<c::OffTrack>();
+        let mut lines = Vec::new();
+        let config = gameworld.specs_world.read_resource::<RaceConfig>();
+        if let (GameMode::Race, Some(progress)) = (config.mode, progresses.get(player_entity)) {
+            if progress.finished {
+                lines.push(format!("FINISHED in {:.2}s", progress.time));
+            } else {
+                lines.push(format!("Lap {}/{}  {:.1}s", progress.lap.max(1).min(config.laps), config.laps, progress.time));
+            }
+        }
+        if config.mode == GameMode::Exam {
+            let exam = gameworld.specs_world.read_resource::<Exam>();
+            let mut line = format!("Driving test: {} penalty points", exam.points());
+            if let Some(last) = exam.violations.last() {
+                line.push_str(&format!(", last: {}", last.description()));
+            }
+            lines.push(line);
+        }
+        if self.bay_mesh.is_some() {
+            let parking = gameworld.specs_world.read_resource::<ParkingChallenge>();
+            lines.push(if parking.finished {
+                format!("PARKED! Score {}", parking.score())
+            } else {
+                format!(
+                    "Park in the bay: {:.1}s, {} collisions, {} direction changes",
+                    parking.elapsed, parking.collisions, parking.direction_changes
+                )
+            });
+        }
+        if let Some(off_track) = off_tracks.get(player_entity) {
+            if off_track.penalty > 0.0 {
+                lines.push(format!("Track limits: +{:.1}s", off_track.penalty));
+            }
+            if off_track.invalid_lap.is_some() {
+                lines.push(String::from("LAP INVALID"));
+            }
+        }
+        if let Some(progress) = progresses.get(player_entity) {
+            if progress.penalty > 0.0 {
+                lines.push(format!("Pedestrians hit: +{:.1}s", progress.penalty));
+            }
+        }
+        for (i, line) in lines.iter().enumerate() {
+            let text = graphics::Text::new(line.as_str());
+            let params = graphics::DrawParam::default()
+                .dest(na::Point2::new(0.0, 20.0 + i as f32 * 20.0))
+                .color(graphics::Color::new(1.0, 0.8, 0.2, 1.0));
+            graphics::draw(ctx, &text, params)?;
+        }
+
+        Ok(())
+    }
+
+    /// The race is over once every player has finished.
+    fn race_over(&self, world: &World) -> bool {
+        let progresses = world.specs_world.read_storage::<c::RaceProgress>();
+        world.specs_world.read_resource::<RaceConfig>().mode == GameMode::Race
+            && self.players.iter().all(|p| progresses.get(p.entity).map_or(false, |p| p.finished))
+    }
+
+    /// Ranks the cars: finishers by time including penalties, then the
+    /// rest by how far they got.
+    fn race_result(&self, world: &World) -> RaceResult {
+        let progresses = world.specs_world.read_storage::<c::RaceProgress>();
+        let off_tracks = world.specs_world.read_storage::<c::OffTrack>();
+        let players = world.specs_world.read_storage::<c::Player>();
+        let mut ranked: Vec<(Finisher, f32)> = self.racers.iter()
+            .filter_map(|&(entity, ref name)| {
+                let progress = progresses.get(entity)?;
+                let finisher = Finisher {
+                    name: name.clone(),
+                    player: players.get(entity).is_some(),
+                    time: if progress.finished { Some(progress.time) } else { None },
+                    penalty: progress.penalty + off_tracks.get(entity).map_or(0.0, |o| o.penalty),
+                    laps: progress.laps.clone(),
+                };
+                Some((finisher, progress.total))
+            })
+            .collect();
+        ranked.sort_by(|(a, a_total), (b, b_total)| match (a.total_time(), b.total_time()) {
+            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap(),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => b_total.partial_cmp(a_total).unwrap(),
+        });
+        RaceResult {
+            map: world.specs_world.read_resource::<RaceConfig>().map.clone(),
+            finishers: ranked.into_iter().map(|(f, _)| f).collect(),
+            new_record: false,
+        }
+    }
+}
+
+//  fn camera_draw(ctx: &mut ggez::Context, drawable: &graphics::Drawable, params: graphics::DrawParam) -> ggez::GameResult<()> {
+//     Ok(())
+// }
+
+impl scene::Scene<World, input::Event> for LevelScene {
+    fn update(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> scenes::Switch {
+        match gameworld.pause_choice.take() {
+            Some(PauseChoice::Restart) => {
+                let config = gameworld.specs_world.read_resource::<RaceConfig>().clone();
+                let mut loading = LoadingScene::new(gameworld, config);
+                if let Some(championship) = self.championship.take() {
+                    loading = loading.with_championship(championship);
+                }
+                return scene::SceneSwitch::Replace(Box::new(loading));
+            }
+            Some(PauseChoice::Quit) => {
+                return scene::SceneSwitch::Pop;
+            }
+            Some(PauseChoice::Resume) | None => (),
+        }
+        if self.pausing {
+            self.pausing = false;
+            gameworld.transition = Some(Transition::Cut);
+            return scene::SceneSwitch::Push(Box::new(PauseScene::new()));
+        }
+        if self.done || gameworld.peer_gone {
+            return scene::SceneSwitch::Pop;
+        }
+        if gameworld.waiting_for_peer {
+            return scene::SceneSwitch::None;
+        }
+
+        {
+            let mut controls = gameworld.specs_world.write_storage::<c::Controls>();
+            let motions = gameworld.specs_world.read_storage::<c::Motion>();
+            let parked = gameworld.specs_world.read_resource::<ParkingChallenge>().finished;
+            // over the network both machines have to steer the same
+            let steering_assist = gameworld.settings.assists.steering
+                && gameworld.specs_world.read_resource::<RaceConfig>().local_player.is_none();
+            for (id, player) in self.players.iter_mut().enumerate() {
+                let frame = gameworld.frames[id];
+                let player_controls = controls.get_mut(player.entity).expect("Player w/o controls?");
+                player_controls.steering = frame.steering;
+                if steering_assist {
+                    let speed = motions.get(player.entity).map_or(0.0, |m| m.acceleration.y.max(0.0));
+                    player_controls.steering *= 1.0 - STEERING_ASSIST * (speed / MAX_VELOCITY).min(1.0);
+                }
+                player_controls.throttle = frame.throttle;
+                player_controls.fire = frame.fire;
+                player_controls.reset = frame.reset && !player.reset_held;
+                player.reset_held = frame.reset;
+
+                // a parked car stays parked
+                if parked {
+                    *player_controls = c::Controls::default();
+                }
+            }
+        }
+        self.dispatcher.dispatch(&gameworld.specs_world.res);
+        gameworld.specs_world.maintain();
+
+        self.update_collisions(gameworld);
+        self.update_shots(gameworld);
+        self.update_pedestrians(gameworld);
+        self.maintain_traffic(gameworld);
+        self.play_sounds(gameworld);
+
+        let exam_finished = gameworld.specs_world.read_resource::<Exam>().finished;
+        if exam_finished {
+            let exam = gameworld.specs_world.read_resource::<Exam>().clone();
+            return scene::SceneSwitch::Replace(Box::new(scenes::results::ExamResultsScene::new(exam)));
+        }
+        if self.race_over(gameworld) {
+            let mut result = self.race_result(gameworld);
+            let config = gameworld.specs_world.read_resource::<RaceConfig>().clone();
+            let mut records = Records::load(ctx);
+            // finishers come fastest first
+            let best = result.finishers.iter().filter(|f| f.player).find_map(Finisher::total_time);
+            if let Some(time) = best {
+                if records.submit(&config, time) {
+                    records.save(ctx);
+                    result.new_record = true;
+                }
+            }
+            let mut championship = self.championship.take();
+            let awarded = championship.as_mut().map(|championship| {
+                let awarded = championship.score(&result);
+                championship.save(ctx);
+                awarded
+            });
+            let results = scenes::results::RaceResultsScene::new(result, config, awarded, championship);
+            gameworld.transition = Some(Transition::Wipe(0.8));
+            return scene::SceneSwitch::Replace(Box::new(results));
+        }
+        scene::SceneSwitch::None
+    }
+
+    fn draw(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let window = graphics::Rect::new(0.0, 0.0, VIEW_HALF_WIDTH * 2.0, VIEW_HALF_HEIGHT * 2.0);
+        for i in 0..self.players.len() {
+            let viewport = match self.players[i].viewport {
+                Some(viewport) => viewport,
+                None => continue,
+            };
+            if let Some(ref canvas) = self.players[i].canvas {
+                graphics::set_canvas(ctx, Some(canvas));
+                graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, viewport.w, viewport.h))?;
+                graphics::clear(ctx, graphics::Color::from((0.0, 0.0, 0.4, 0.0)));
+            }
+            self.draw_view(gameworld, ctx, i)?;
+            if let Some(ref canvas) = self.players[i].canvas {
+                graphics::set_canvas(ctx, None);
+                graphics::set_screen_coordinates(ctx, window)?;
+                graphics::draw(ctx, canvas, graphics::DrawParam::default().dest(na::Point2::new(viewport.x, viewport.y)))?;
+            }
+        }
         Ok(())
     }
 
@@ -250,30 +1015,14 @@ impl scene::Scene<World, input::Event> for LevelScene {
 
     fn input(&mut self, gameworld: &mut World, ev: input::Event, _started: bool) {
         debug!("Input: {:?}", ev);
-        if gameworld.input.get_button_pressed(input::Button::Menu) {
-            self.done = true;
-        }
-        let mut motions = gameworld.specs_world.write_storage::<c::Motion>();
-        let motion = motions.get_mut(self.player_entity).expect("Player w/o motion?");
-        if !motion.is_blocked {
-
-            // update steering to set orientation from 0 to pi
-            let steering_input = gameworld.input.get_axis(input::Axis::Horz);
-            motion.orientation += steering_input / 4.0;
-            if motion.orientation < 0.0 {
-                motion.orientation += TAU;
-            } else if motion.orientation > TAU {
-                motion.orientation -= TAU;
+        if gameworld.inputs[0].get_button_pressed(input::Button::Menu) {
+            // a race over the network can't stop for one player, so
+            // Menu just leaves it
+            if gameworld.specs_world.read_resource::<RaceConfig>().local_player.is_some() {
+                self.done = true;
+            } else {
+                self.pausing = true;
             }
-
-            // this is y-velocity, not acceleration
-            let accel_input = gameworld.input.get_axis(input::Axis::Vert);
-            motion.acceleration.y += accel_input;
-            motion.acceleration.y = motion.acceleration.y.max(MIN_VELOCITY).min(MAX_VELOCITY);
-
         }
-        // calculate new velocity
-        motion.update();
-
     }
 }
@@ -0,0 +1,104 @@
+//! Picking a map to race on.
+
+use ggez;
+use ggez::graphics;
+use ggez_goodies::scene;
+use log::*;
+
+use ggez::nalgebra as na;
+
+use crate::input;
+use crate::race::RaceConfig;
+use crate::records::Records;
+use crate::scenes;
+use crate::scenes::loading::LoadingScene;
+use crate::scenes::menu::MenuList;
+use crate::scenes::results::format_time;
+use crate::track::MapInfo;
+use crate::world::World;
+
+/// Lists every map in the resource directory, with the best time on it,
+/// and starts a race on the chosen one.
+pub struct LevelSelectScene {
+    maps: Vec<MapInfo>,
+    /// Indices into `maps`.
+    list: MenuList<usize>,
+    chosen: Option<usize>,
+    back: bool,
+    /// The race to start, apart from the map.
+    config: RaceConfig,
+}
+
+impl LevelSelectScene {
+    pub fn new(ctx: &mut ggez::Context, world: &mut World, config: RaceConfig) -> Self {
+        let maps = world.maps().to_vec();
+        let records = Records::load(ctx);
+        let mut list = MenuList::default();
+        for (i, map) in maps.iter().enumerate() {
+            let race = RaceConfig { map: map.file.clone(), ..config.clone() };
+            let best = records.best(&race).map(|time| format_time(Some(time)));
+            let label = format!("{:<24} {}", map.name, best.unwrap_or_else(|| String::from("--:--.--")));
+            list = list.with(i, &label);
+        }
+        if let Some(i) = maps.iter().position(|map| map.file == config.map) {
+            list.select(i);
+        }
+        LevelSelectScene { maps, list, chosen: None, back: false, config }
+    }
+}
+
+impl scene::Scene<World, input::Event> for LevelSelectScene {
+    fn update(&mut self, gameworld: &mut World, _ctx: &mut ggez::Context) -> scenes::Switch {
+        if self.back {
+            return scene::SceneSwitch::Pop;
+        }
+        match self.chosen.take() {
+            Some(i) => {
+                let config = RaceConfig {
+                    map: self.maps[i].file.clone(),
+                    ..self.config.clone()
+                };
+                // leaving the race goes back to the main menu
+                scene::SceneSwitch::Replace(Box::new(LoadingScene::new(gameworld, config)))
+            }
+            None => scene::SceneSwitch::None,
+        }
+    }
+
+    fn draw(&mut self, _gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let mut title = graphics::Text::new("SELECT TRACK");
+        title.set_font(graphics::Font::default(), graphics::Scale::uniform(48.0));
+        graphics::draw(ctx, &title, graphics::DrawParam::default().dest(na::Point2::new(40.0, 40.0)))?;
+        if self.maps.is_empty() {
+            let text = graphics::Text::new("No maps found");
+            return graphics::draw(ctx, &text, graphics::DrawParam::default().dest(na::Point2::new(40.0, 160.0)));
+        }
+        self.list.draw(ctx, 40.0, 160.0)?;
+
+        let map = &self.maps[self.list.selected()];
+        let mut about = map.description.clone();
+        if !map.author.is_empty() {
+            about.push_str(&format!("\nby {}", map.author));
+        }
+        let text = graphics::Text::new(about);
+        graphics::draw(ctx, &text, graphics::DrawParam::default().dest(na::Point2::new(40.0, 500.0)))
+    }
+
+    fn name(&self) -> &str {
+        "LevelSelectScene"
+    }
+
+    fn input(&mut self, _gameworld: &mut World, ev: input::Event, started: bool) {
+        debug!("Input: {:?}", ev);
+        if let input::Event::Button(input::Button::Back) = ev {
+            self.back |= started;
+            return;
+        }
+        if self.maps.is_empty() {
+            return;
+        }
+        if let Some(i) = self.list.input(&ev, started) {
+            self.chosen = Some(i);
+        }
+    }
+}
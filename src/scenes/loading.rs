@@ -0,0 +1,223 @@
+//! Loading a level a bit at a time, with a progress bar, so the window
+//! doesn't freeze while it happens.
+//!
+//! The map file is parsed on a worker thread.  Images have to be made on
+//! the main thread, where the graphics context is, so they are loaded one
+//! per update.  There are no sound or font files to load yet.
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::thread;
+
+use ggez;
+use ggez::graphics;
+use ggez_goodies::scene;
+use ggez_goodies::tilemap::tiled;
+use log::*;
+
+use ggez::nalgebra as na;
+
+use crate::championship::Championship;
+use crate::input;
+use crate::race::RaceConfig;
+use crate::resources;
+use crate::scenes;
+use crate::scenes::level::{self, LevelScene};
+use crate::world::World;
+
+const BAR_WIDTH: f32 = 600.0;
+const BAR_HEIGHT: f32 = 24.0;
+
+/// Something left to load before the level can start.
+enum Asset {
+    /// A tileset image, by the path the map gives it.
+    Tileset(String),
+    /// A sprite image, which goes into the resource store.
+    Sprite(&'static str),
+}
+
+/// Sits on the stack while a level loads, and replaces itself with the
+/// level when everything is ready.
+pub struct LoadingScene {
+    config: RaceConfig,
+    championship: Option<Championship>,
+    /// Hands over the parsed map once the worker thread is done.
+    parsed: mpsc::Receiver<Result<tiled::Map, String>>,
+    map: Option<tiled::Map>,
+    pending: Vec<Asset>,
+    tilesets: HashMap<String, graphics::Image>,
+    /// Steps done and steps in all, counting the map and setting up the
+    /// level as one each.  Only known once the map is parsed.
+    done: usize,
+    total: usize,
+    failed: bool,
+}
+
+impl LoadingScene {
+    /// Starts parsing the race's map straight away.
+    pub fn new(world: &World, config: RaceConfig) -> Self {
+        let path = world.resource_dir.join(&config.map);
+        let (sender, parsed) = mpsc::channel();
+        thread::spawn(move || {
+            let map = tiled::parse_file(&path).map_err(|e| format!("{:?}", e));
+            // nobody's waiting any more if the scene is gone
+            let _ = sender.send(map);
+        });
+        LoadingScene {
+            config,
+            championship: None,
+            parsed,
+            map: None,
+            pending: Vec::new(),
+            tilesets: HashMap::new(),
+            done: 0,
+            total: 0,
+            failed: false,
+        }
+    }
+
+    /// Makes the race a round of a championship.
+    pub fn with_championship(mut self, championship: Championship) -> Self {
+        self.championship = Some(championship);
+        self
+    }
+
+    /// How far along loading is, from 0.0 to 1.0.
+    fn progress(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.done as f32 / self.total as f32
+        }
+    }
+
+    /// Checks whether the worker thread has finished with the map, and
+    /// works out what else has to be loaded once it has.
+    fn receive_map(&mut self) {
+        let map = match self.parsed.try_recv() {
+            Ok(Ok(map)) => map,
+            Ok(Err(e)) => {
+                warn!("Couldn't load map {}: {}", self.config.map, e);
+                self.failed = true;
+                return;
+            }
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                warn!("Map loader for {} went away", self.config.map);
+                self.failed = true;
+                return;
+            }
+        };
+        for tileset in &map.tilesets {
+            for image in &tileset.images {
+                if !self.pending.iter().any(|a| match a {
+                    Asset::Tileset(source) => *source == image.source,
+                    Asset::Sprite(_) => false,
+                }) {
+                    self.pending.push(Asset::Tileset(image.source.clone()));
+                }
+            }
+        }
+        self.pending.extend(level::SPRITE_IMAGES.iter().map(|&path| Asset::Sprite(path)));
+        // last first, so they come off the end in order
+        self.pending.reverse();
+        self.total = self.pending.len() + 2;
+        self.done = 1;
+        self.map = Some(map);
+    }
+
+    fn load(&mut self, world: &mut World, ctx: &mut ggez::Context, asset: Asset) {
+        match asset {
+            Asset::Tileset(source) => {
+                let mut path_in_resources = String::from("/");
+                path_in_resources.push_str(&source);
+                match graphics::Image::new(ctx, path_in_resources) {
+                    Ok(image) => {
+                        self.tilesets.insert(source, image);
+                    }
+                    Err(e) => {
+                        warn!("Couldn't load tileset image {}: {}", source, e);
+                        self.failed = true;
+                    }
+                }
+            }
+            Asset::Sprite(path) => {
+                if let Err(e) = world.resources.get::<resources::Image>(&resources::Key::from_path(path), ctx) {
+                    warn!("Couldn't load image {}: {:?}", path, e);
+                    self.failed = true;
+                }
+            }
+        }
+        self.done += 1;
+    }
+}
+
+impl scene::Scene<World, input::Event> for LoadingScene {
+    fn update(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> scenes::Switch {
+        if self.failed {
+            // back to wherever the race was started from
+            return scene::SceneSwitch::Pop;
+        }
+        if self.map.is_none() {
+            self.receive_map();
+            return scene::SceneSwitch::None;
+        }
+        if let Some(asset) = self.pending.pop() {
+            self.load(gameworld, ctx, asset);
+            return scene::SceneSwitch::None;
+        }
+
+        // this scene's id is taken by now, so the level's entities get
+        // tagged with the id it will have once it replaces this one
+        let map = self.map.take().expect("no map to start the level on");
+        let mut level = match LevelScene::new(ctx, gameworld, self.config.clone(), map, &self.tilesets) {
+            Ok(level) => level,
+            Err(e) => {
+                warn!("Couldn't start the level: {}", e);
+                return scene::SceneSwitch::Pop;
+            }
+        };
+        if let Some(championship) = self.championship.take() {
+            level = level.with_championship(championship);
+        }
+        self.done = self.total;
+        scene::SceneSwitch::Replace(Box::new(level))
+    }
+
+    fn draw(&mut self, _gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let screen = graphics::screen_coordinates(ctx);
+        let mut title = graphics::Text::new("LOADING");
+        title.set_font(graphics::Font::default(), graphics::Scale::uniform(48.0));
+        graphics::draw(ctx, &title, graphics::DrawParam::default().dest(na::Point2::new(40.0, 40.0)))?;
+        let text = graphics::Text::new(self.config.map.clone());
+        graphics::draw(ctx, &text, graphics::DrawParam::default().dest(na::Point2::new(40.0, 110.0)))?;
+
+        let x = (screen.w - BAR_WIDTH) / 2.0;
+        let y = (screen.h - BAR_HEIGHT) / 2.0;
+        let white = graphics::Color::from_rgb(255, 255, 255);
+        let outline = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::stroke(2.0),
+            graphics::Rect::new(x, y, BAR_WIDTH, BAR_HEIGHT),
+            white,
+        )?;
+        graphics::draw(ctx, &outline, graphics::DrawParam::default())?;
+        let filled = BAR_WIDTH * self.progress();
+        if filled > 0.0 {
+            let bar = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                graphics::Rect::new(x, y, filled, BAR_HEIGHT),
+                white,
+            )?;
+            graphics::draw(ctx, &bar, graphics::DrawParam::default())?;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "LoadingScene"
+    }
+
+    fn input(&mut self, _gameworld: &mut World, _ev: input::Event, _started: bool) {}
+}
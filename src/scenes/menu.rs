@@ -0,0 +1,176 @@
+//! The main menu, and a list of choices other menus can use too.
+
+use ggez;
+use ggez::graphics;
+use ggez_goodies::scene;
+use log::*;
+
+use ggez::nalgebra as na;
+
+use crate::input;
+use crate::race::RaceConfig;
+use crate::scenes;
+use crate::scenes::level_select::LevelSelectScene;
+use crate::scenes::settings::SettingsScene;
+use crate::world::World;
+
+const SELECTED_COLOR: (f32, f32, f32, f32) = (1.0, 0.8, 0.2, 1.0);
+
+/// A vertical list of choices, moved through with the vertical axis.
+pub struct MenuList<T> {
+    entries: Vec<(T, String)>,
+    selected: usize,
+}
+
+impl<T> Default for MenuList<T> {
+    fn default() -> Self {
+        MenuList { entries: Vec::new(), selected: 0 }
+    }
+}
+
+impl<T: Copy> MenuList<T> {
+    pub fn with(mut self, entry: T, label: &str) -> Self {
+        self.entries.push((entry, label.to_owned()));
+        self
+    }
+
+    pub fn selected(&self) -> T {
+        self.entries[self.selected].0
+    }
+
+    /// Moves the selection to the given entry, if it's there.
+    pub fn select(&mut self, entry: T)
+    where
+        T: PartialEq,
+    {
+        if let Some(i) = self.entries.iter().position(|e| e.0 == entry) {
+            self.selected = i;
+        }
+    }
+
+    /// Changes what an entry says, e.g. when it shows a setting.
+    pub fn set_label(&mut self, entry: T, label: String)
+    where
+        T: PartialEq,
+    {
+        for e in self.entries.iter_mut().filter(|e| e.0 == entry) {
+            e.1 = label.clone();
+        }
+    }
+
+    /// Moves the selection one entry up or down, wrapping around.
+    pub fn step(&mut self, up: bool) {
+        let n = self.entries.len();
+        if n > 0 {
+            self.selected = if up { (self.selected + n - 1) % n } else { (self.selected + 1) % n };
+        }
+    }
+
+    /// Handles an input event, returning the chosen entry when Select is
+    /// pressed.
+    pub fn input(&mut self, ev: &input::Event, started: bool) -> Option<T> {
+        if !started {
+            return None;
+        }
+        match *ev {
+            input::Event::Axis(input::Axis::Vert, positive) => {
+                self.step(positive);
+                None
+            }
+            input::Event::Button(input::Button::Select) => Some(self.selected()),
+            _ => None,
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut ggez::Context, x: f32, y: f32) -> ggez::GameResult<()> {
+        self.draw_spaced(ctx, x, y, 36.0, 24.0)
+    }
+
+    /// Draws the entries with the given distance between them and the
+    /// given font size, for longer lists.
+    pub fn draw_spaced(&self, ctx: &mut ggez::Context, x: f32, y: f32, spacing: f32, size: f32) -> ggez::GameResult<()> {
+        for (i, &(_, ref label)) in self.entries.iter().enumerate() {
+            let color = if i == self.selected { SELECTED_COLOR } else { (1.0, 1.0, 1.0, 1.0) };
+            let mut text = graphics::Text::new(if i == self.selected { format!("> {}", label) } else { format!("  {}", label) });
+            text.set_font(graphics::Font::default(), graphics::Scale::uniform(size));
+            let dest = na::Point2::new(x, y + i as f32 * spacing);
+            graphics::draw(ctx, &text, graphics::DrawParam::default().dest(dest).color(color.into()))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Entry {
+    Play,
+    TimeTrial,
+    Settings,
+    Quit,
+}
+
+/// The first scene, and the one every level goes back to.
+pub struct MenuScene {
+    list: MenuList<Entry>,
+    chosen: Option<Entry>,
+    /// The race to start, as set on the command line.
+    config: RaceConfig,
+}
+
+impl MenuScene {
+    pub fn new(config: RaceConfig) -> Self {
+        let list = MenuList::default()
+            .with(Entry::Play, "Play")
+            .with(Entry::TimeTrial, "Time Trial")
+            .with(Entry::Settings, "Settings")
+            .with(Entry::Quit, "Quit");
+        MenuScene { list, chosen: None, config }
+    }
+}
+
+impl scene::Scene<World, input::Event> for MenuScene {
+    fn update(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> scenes::Switch {
+        match self.chosen.take() {
+            Some(Entry::Play) => {
+                let select = LevelSelectScene::new(ctx, gameworld, self.config.clone());
+                scene::SceneSwitch::Push(Box::new(select))
+            }
+            Some(Entry::TimeTrial) => {
+                let config = RaceConfig {
+                    players: self.config.players,
+                    ..RaceConfig::time_trial()
+                };
+                scene::SceneSwitch::Push(Box::new(LevelSelectScene::new(ctx, gameworld, config)))
+            }
+            Some(Entry::Quit) => {
+                ggez::event::quit(ctx);
+                scene::SceneSwitch::None
+            }
+            Some(Entry::Settings) => scene::SceneSwitch::Push(Box::new(SettingsScene::new(gameworld))),
+            None => scene::SceneSwitch::None,
+        }
+    }
+
+    fn draw(&mut self, _gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let mut title = graphics::Text::new("EZ DRIVING");
+        title.set_font(graphics::Font::default(), graphics::Scale::uniform(48.0));
+        graphics::draw(ctx, &title, graphics::DrawParam::default().dest(na::Point2::new(40.0, 40.0)))?;
+        self.list.draw(ctx, 40.0, 160.0)
+    }
+
+    fn name(&self) -> &str {
+        "MenuScene"
+    }
+
+    fn input(&mut self, _gameworld: &mut World, ev: input::Event, started: bool) {
+        debug!("Input: {:?}", ev);
+        if let input::Event::Button(input::Button::Back) = ev {
+            if started {
+                self.list.select(Entry::Quit);
+            }
+            return;
+        }
+        if let Some(entry) = self.list.input(&ev, started) {
+            self.chosen = Some(entry);
+        }
+    }
+}
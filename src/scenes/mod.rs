@@ -4,9 +4,17 @@ use crate::input;
 use crate::world::World;
 
 pub mod level;
+pub mod level_select;
+pub mod loading;
+pub mod menu;
+pub mod pause;
+pub mod results;
+pub mod settings;
+pub mod stack;
+
+pub use self::stack::{Stack, Transition};
 
 // Shortcuts for our scene type.
 pub type Switch = scene::SceneSwitch<World, input::Event>;
-pub type Stack = scene::SceneStack<World, input::Event>;
 // Useless, since you can't impl type aliases.  :|
 //pub trait Scene = scene::Scene<World, input::Event>;
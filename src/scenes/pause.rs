@@ -0,0 +1,101 @@
+//! The pause menu, drawn over the frozen level.
+
+use ggez;
+use ggez::graphics;
+use ggez_goodies::scene;
+use log::*;
+
+use ggez::nalgebra as na;
+
+use crate::input;
+use crate::scenes;
+use crate::scenes::Transition;
+use crate::scenes::menu::MenuList;
+use crate::world::World;
+
+/// What the player picked in the pause menu.  Left in
+/// `World::pause_choice` for the level underneath to act on once the
+/// pause scene is gone.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PauseChoice {
+    Resume,
+    Restart,
+    Quit,
+}
+
+/// Sits on top of the level on the scene stack.  Only the top scene gets
+/// updated, so the level stands still until this is popped.
+pub struct PauseScene {
+    list: MenuList<PauseChoice>,
+    chosen: Option<PauseChoice>,
+    /// Darkens the level behind the menu.
+    shade: Option<graphics::Mesh>,
+}
+
+impl PauseScene {
+    pub fn new() -> Self {
+        let list = MenuList::default()
+            .with(PauseChoice::Resume, "Resume")
+            .with(PauseChoice::Restart, "Restart")
+            .with(PauseChoice::Quit, "Quit to menu");
+        PauseScene { list, chosen: None, shade: None }
+    }
+}
+
+impl scene::Scene<World, input::Event> for PauseScene {
+    fn update(&mut self, gameworld: &mut World, _ctx: &mut ggez::Context) -> scenes::Switch {
+        match self.chosen.take() {
+            Some(choice) => {
+                gameworld.pause_choice = Some(choice);
+                // the level fades out itself if it's leaving
+                gameworld.transition = Some(Transition::Cut);
+                scene::SceneSwitch::Pop
+            }
+            None => scene::SceneSwitch::None,
+        }
+    }
+
+    fn draw(&mut self, _gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        if self.shade.is_none() {
+            let screen = graphics::screen_coordinates(ctx);
+            let shade = graphics::Mesh::new_rectangle(
+                ctx,
+                graphics::DrawMode::fill(),
+                screen,
+                graphics::Color::new(0.0, 0.0, 0.0, 0.6),
+            )?;
+            self.shade = Some(shade);
+        }
+        if let Some(ref shade) = self.shade {
+            graphics::draw(ctx, shade, graphics::DrawParam::default())?;
+        }
+
+        let mut title = graphics::Text::new("PAUSED");
+        title.set_font(graphics::Font::default(), graphics::Scale::uniform(48.0));
+        graphics::draw(ctx, &title, graphics::DrawParam::default().dest(na::Point2::new(40.0, 40.0)))?;
+        self.list.draw(ctx, 40.0, 160.0)
+    }
+
+    fn name(&self) -> &str {
+        "PauseScene"
+    }
+
+    fn input(&mut self, _gameworld: &mut World, ev: input::Event, started: bool) {
+        debug!("Input: {:?}", ev);
+        match ev {
+            // the button that paused also resumes
+            input::Event::Button(input::Button::Menu) | input::Event::Button(input::Button::Back) if started => {
+                self.chosen = Some(PauseChoice::Resume);
+            }
+            _ => {
+                if let Some(choice) = self.list.input(&ev, started) {
+                    self.chosen = Some(choice);
+                }
+            }
+        }
+    }
+
+    fn draw_previous(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,319 @@
+//! Scenes shown once a level is over.
+
+use ggez;
+use ggez::graphics;
+use ggez_goodies::scene;
+use log::*;
+
+use ggez::nalgebra as na;
+
+use crate::championship::Championship;
+use crate::exam::Exam;
+use crate::input;
+use crate::race::{RaceConfig, RaceResult};
+use crate::scenes;
+use crate::scenes::loading::LoadingScene;
+use crate::scenes::menu::MenuList;
+use crate::world::World;
+
+/// Whether the event is a press of any of the buttons that get out of a
+/// results screen.
+fn is_continue(ev: &input::Event, started: bool) -> bool {
+    match *ev {
+        input::Event::Button(input::Button::Select)
+        | input::Event::Button(input::Button::Back)
+        | input::Event::Button(input::Button::Menu) => started,
+        _ => false,
+    }
+}
+
+pub fn format_time(time: Option<f32>) -> String {
+    match time {
+        Some(time) => format!("{}:{:05.2}", (time / 60.0) as u32, time % 60.0),
+        None => String::from("DNF"),
+    }
+}
+
+fn draw_title(ctx: &mut ggez::Context, title: &str) -> ggez::GameResult<()> {
+    let mut text = graphics::Text::new(title);
+    text.set_font(graphics::Font::default(), graphics::Scale::uniform(48.0));
+    graphics::draw(ctx, &text, graphics::DrawParam::default().dest(na::Point2::new(40.0, 40.0)))
+}
+
+fn draw_line(ctx: &mut ggez::Context, line: usize, text: String) -> ggez::GameResult<()> {
+    let text = graphics::Text::new(text);
+    graphics::draw(ctx, &text, graphics::DrawParam::default().dest(na::Point2::new(40.0, 110.0 + line as f32 * 20.0)))
+}
+
+/// What to do after a race.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RaceAction {
+    /// On to the next race of the championship.
+    Continue,
+    Retry,
+    NextTrack,
+    Menu,
+}
+
+/// The finishing order of a race with everyone's laps, and the points
+/// handed out for it when it's part of a championship.  Moves on to the
+/// next race of the championship, or its podium after the last one.
+pub struct RaceResultsScene {
+    result: RaceResult,
+    /// The race that was run, to run it again.
+    config: RaceConfig,
+    /// Points for each finisher, in a championship.
+    awarded: Option<Vec<u32>>,
+    championship: Option<Championship>,
+    list: MenuList<RaceAction>,
+    chosen: Option<RaceAction>,
+}
+
+impl RaceResultsScene {
+    pub fn new(result: RaceResult, config: RaceConfig, awarded: Option<Vec<u32>>, championship: Option<Championship>) -> Self {
+        // a championship race counts as it went
+        let list = match championship {
+            Some(ref championship) if championship.is_over() => MenuList::default()
+                .with(RaceAction::Continue, "Final standings")
+                .with(RaceAction::Menu, "Menu"),
+            Some(_) => MenuList::default()
+                .with(RaceAction::Continue, "Next race")
+                .with(RaceAction::Menu, "Menu"),
+            None => MenuList::default()
+                .with(RaceAction::Retry, "Retry")
+                .with(RaceAction::NextTrack, "Next track")
+                .with(RaceAction::Menu, "Menu"),
+        };
+        RaceResultsScene { result, config, awarded, championship, list, chosen: None }
+    }
+
+    /// The map after this one in the track list, going round to the first
+    /// after the last.
+    fn next_map(&self, world: &mut World) -> String {
+        let maps = world.maps();
+        match maps.iter().position(|map| map.file == self.config.map) {
+            Some(i) => maps[(i + 1) % maps.len()].file.clone(),
+            None => self.config.map.clone(),
+        }
+    }
+
+    /// The laps of every finisher, a column per lap.  Invalidated laps
+    /// are marked with a star and the best valid one with an arrow.
+    fn lap_table(&self) -> Vec<String> {
+        let laps = self.result.finishers.iter().map(|f| f.laps.len()).max().unwrap_or(0);
+        if laps == 0 {
+            return Vec::new();
+        }
+        let mut header = format!("{:<13}", "");
+        for lap in 1..=laps {
+            header.push_str(&format!("{:>10}", format!("Lap {}", lap)));
+        }
+        let mut table = vec![header];
+        for finisher in &self.result.finishers {
+            let best = finisher.best_lap();
+            let mut row = format!("{:<13}", finisher.name);
+            for lap in &finisher.laps {
+                let mark = if !lap.valid {
+                    "*"
+                } else if Some(lap.time) == best {
+                    "<"
+                } else {
+                    " "
+                };
+                row.push_str(&format!("{:>9}{}", format_time(Some(lap.time)), mark));
+            }
+            table.push(row);
+        }
+        table
+    }
+}
+
+impl scene::Scene<World, input::Event> for RaceResultsScene {
+    fn update(&mut self, gameworld: &mut World, _ctx: &mut ggez::Context) -> scenes::Switch {
+        match self.chosen.take() {
+            Some(RaceAction::Continue) => match self.championship.take() {
+                Some(championship) => match championship.race_config() {
+                    Some(config) => {
+                        let loading = LoadingScene::new(gameworld, config).with_championship(championship);
+                        scene::SceneSwitch::Replace(Box::new(loading))
+                    }
+                    None => scene::SceneSwitch::Replace(Box::new(PodiumScene::new(championship))),
+                },
+                None => scene::SceneSwitch::Pop,
+            },
+            Some(RaceAction::Retry) => {
+                let loading = LoadingScene::new(gameworld, self.config.clone());
+                scene::SceneSwitch::Replace(Box::new(loading))
+            }
+            Some(RaceAction::NextTrack) => {
+                let config = RaceConfig {
+                    map: self.next_map(gameworld),
+                    ..self.config.clone()
+                };
+                scene::SceneSwitch::Replace(Box::new(LoadingScene::new(gameworld, config)))
+            }
+            Some(RaceAction::Menu) => scene::SceneSwitch::Pop,
+            None => scene::SceneSwitch::None,
+        }
+    }
+
+    fn draw(&mut self, _gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        draw_title(ctx, "RESULTS")?;
+        let mut heading = self.result.map.clone();
+        if self.result.new_record {
+            heading.push_str("  NEW RECORD!");
+        }
+        draw_line(ctx, 0, heading)?;
+        let mut line = 2;
+        for (i, finisher) in self.result.finishers.iter().enumerate() {
+            let mut text = format!(
+                "{}. {:<10} {:>8}  best lap {:>8}",
+                i + 1,
+                finisher.name,
+                format_time(finisher.total_time()),
+                finisher.best_lap().map_or(String::from("-"), |time| format_time(Some(time)))
+            );
+            if finisher.penalty > 0.0 {
+                text.push_str(&format!("  (+{:.1}s penalty)", finisher.penalty));
+            }
+            if let Some(points) = self.awarded.as_ref().and_then(|a| a.get(i)) {
+                text.push_str(&format!("  +{} pts", points));
+            }
+            draw_line(ctx, line, text)?;
+            line += 1;
+        }
+        line += 1;
+        for row in self.lap_table() {
+            draw_line(ctx, line, row)?;
+            line += 1;
+        }
+        self.list.draw(ctx, 40.0, 150.0 + line as f32 * 20.0)
+    }
+
+    fn name(&self) -> &str {
+        "RaceResultsScene"
+    }
+
+    fn input(&mut self, _gameworld: &mut World, ev: input::Event, started: bool) {
+        debug!("Input: {:?}", ev);
+        match ev {
+            input::Event::Button(input::Button::Back) | input::Event::Button(input::Button::Menu) if started => {
+                self.list.select(RaceAction::Menu);
+            }
+            _ => {
+                if let Some(action) = self.list.input(&ev, started) {
+                    self.chosen = Some(action);
+                }
+            }
+        }
+    }
+}
+
+/// The final standings of a championship.
+pub struct PodiumScene {
+    done: bool,
+    championship: Championship,
+}
+
+impl PodiumScene {
+    pub fn new(championship: Championship) -> Self {
+        PodiumScene { done: false, championship }
+    }
+}
+
+impl scene::Scene<World, input::Event> for PodiumScene {
+    fn update(&mut self, _gameworld: &mut World, ctx: &mut ggez::Context) -> scenes::Switch {
+        if self.done {
+            Championship::forget(ctx);
+            scene::SceneSwitch::Pop
+        } else {
+            scene::SceneSwitch::None
+        }
+    }
+
+    fn draw(&mut self, _gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        draw_title(ctx, &self.championship.def.name)?;
+        let entries = &self.championship.standings.entries;
+        if let Some(winner) = entries.first() {
+            draw_line(ctx, 0, format!("{} wins the championship!", winner.name))?;
+        }
+        for (i, entry) in entries.iter().enumerate() {
+            let line = format!("{}. {:<10} {:>3} pts  {} wins", i + 1, entry.name, entry.points, entry.wins);
+            draw_line(ctx, i + 2, line)?;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "PodiumScene"
+    }
+
+    fn input(&mut self, _gameworld: &mut World, ev: input::Event, started: bool) {
+        debug!("Input: {:?}", ev);
+        self.done |= is_continue(&ev, started);
+    }
+}
+
+/// The driving test verdict, with every violation the player made.
+pub struct ExamResultsScene {
+    done: bool,
+    exam: Exam,
+}
+
+impl ExamResultsScene {
+    pub fn new(exam: Exam) -> Self {
+        ExamResultsScene { done: false, exam }
+    }
+}
+
+impl scene::Scene<World, input::Event> for ExamResultsScene {
+    fn update(&mut self, _gameworld: &mut World, _ctx: &mut ggez::Context) -> scenes::Switch {
+        if self.done {
+            scene::SceneSwitch::Pop
+        } else {
+            scene::SceneSwitch::None
+        }
+    }
+
+    fn draw(&mut self, _gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let (verdict, color) = if self.exam.passed() {
+            ("PASSED", graphics::Color::new(0.2, 1.0, 0.2, 1.0))
+        } else {
+            ("FAILED", graphics::Color::new(1.0, 0.2, 0.2, 1.0))
+        };
+        let mut title = graphics::Text::new(verdict);
+        title.set_font(graphics::Font::default(), graphics::Scale::uniform(48.0));
+        graphics::draw(ctx, &title, graphics::DrawParam::default().dest(na::Point2::new(40.0, 40.0)).color(color))?;
+
+        let summary = graphics::Text::new(format!(
+            "{} penalty points in {:.1}s{}",
+            self.exam.points(),
+            self.exam.elapsed,
+            if self.exam.timed_out { ", out of time" } else { "" }
+        ));
+        graphics::draw(ctx, &summary, graphics::DrawParam::default().dest(na::Point2::new(40.0, 110.0)))?;
+
+        for (i, violation) in self.exam.violations.iter().enumerate() {
+            let line = graphics::Text::new(format!(
+                "{:6.1}s  ({:4.0}, {:4.0})  {}  +{}",
+                violation.time,
+                violation.position.x,
+                violation.position.y,
+                violation.description(),
+                violation.points()
+            ));
+            let dest = na::Point2::new(40.0, 150.0 + i as f32 * 20.0);
+            graphics::draw(ctx, &line, graphics::DrawParam::default().dest(dest))?;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "ExamResultsScene"
+    }
+
+    fn input(&mut self, _gameworld: &mut World, ev: input::Event, started: bool) {
+        debug!("Input: {:?}", ev);
+        self.done |= is_continue(&ev, started);
+    }
+}
@@ -0,0 +1,263 @@
+//! Changing the settings.  Every change is applied and saved straight
+//! away.
+
+use ggez;
+use ggez::event::KeyCode;
+use ggez::graphics;
+use ggez_goodies::scene;
+use log::*;
+
+use ggez::nalgebra as na;
+
+use crate::input;
+use crate::scenes;
+use crate::scenes::menu::MenuList;
+use crate::settings::{Keys, Settings, RESOLUTIONS};
+use crate::world::World;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum KeyAction {
+    Accelerate,
+    Brake,
+    Left,
+    Right,
+    Fire,
+    Reset,
+}
+
+const KEY_ACTIONS: &[KeyAction] = &[
+    KeyAction::Accelerate,
+    KeyAction::Brake,
+    KeyAction::Left,
+    KeyAction::Right,
+    KeyAction::Fire,
+    KeyAction::Reset,
+];
+
+impl KeyAction {
+    fn key(self, keys: &Keys) -> &str {
+        match self {
+            KeyAction::Accelerate => &keys.accelerate,
+            KeyAction::Brake => &keys.brake,
+            KeyAction::Left => &keys.left,
+            KeyAction::Right => &keys.right,
+            KeyAction::Fire => &keys.fire,
+            KeyAction::Reset => &keys.reset,
+        }
+    }
+
+    fn key_mut(self, keys: &mut Keys) -> &mut String {
+        match self {
+            KeyAction::Accelerate => &mut keys.accelerate,
+            KeyAction::Brake => &mut keys.brake,
+            KeyAction::Left => &mut keys.left,
+            KeyAction::Right => &mut keys.right,
+            KeyAction::Fire => &mut keys.fire,
+            KeyAction::Reset => &mut keys.reset,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Entry {
+    Resolution,
+    Fullscreen,
+    Vsync,
+    SteeringAssist,
+    RacingLine,
+    /// Whose keys are shown below.
+    Player,
+    Key(KeyAction),
+    Back,
+}
+
+/// Left and right change the selected setting; Select toggles it, or
+/// waits for a key to bind.
+pub struct SettingsScene {
+    list: MenuList<Entry>,
+    /// Whose keys are being changed.
+    player: usize,
+    /// Set while waiting for a key to bind.
+    capturing: Option<KeyAction>,
+    /// Changes asked for since the last update: which setting, and -1 or
+    /// 1 for left or right, or 0 for Select.
+    changes: Vec<(Entry, i32)>,
+    back: bool,
+}
+
+impl SettingsScene {
+    pub fn new(world: &World) -> Self {
+        let mut list = MenuList::default()
+            .with(Entry::Resolution, "")
+            .with(Entry::Fullscreen, "")
+            .with(Entry::Vsync, "")
+            .with(Entry::SteeringAssist, "")
+            .with(Entry::RacingLine, "")
+            .with(Entry::Player, "");
+        for &action in KEY_ACTIONS {
+            list = list.with(Entry::Key(action), "");
+        }
+        let mut scene = SettingsScene {
+            list: list.with(Entry::Back, "Back"),
+            player: 0,
+            capturing: None,
+            changes: Vec::new(),
+            back: false,
+        };
+        scene.update_labels(&world.settings);
+        scene
+    }
+
+    fn update_labels(&mut self, settings: &Settings) {
+        let on_off = |on: bool| if on { "On" } else { "Off" };
+        let video = &settings.video;
+        self.list.set_label(Entry::Resolution, format!("Resolution       {}x{}", video.width, video.height));
+        self.list.set_label(Entry::Fullscreen, format!("Fullscreen       {}", on_off(video.fullscreen)));
+        self.list.set_label(Entry::Vsync, format!("Vsync            {}  (on restart)", on_off(video.vsync)));
+        self.list.set_label(Entry::SteeringAssist, format!("Steering assist  {}", on_off(settings.assists.steering)));
+        self.list.set_label(Entry::RacingLine, format!("Racing line      {}", on_off(settings.assists.racing_line)));
+        self.list.set_label(Entry::Player, format!("Controls for     Player {}", self.player + 1));
+        let keys = &settings.controls[self.player];
+        for &action in KEY_ACTIONS {
+            let label = format!("  {:<15}{}", format!("{:?}", action), action.key(keys));
+            self.list.set_label(Entry::Key(action), label);
+        }
+    }
+
+    /// Applies a change to the settings, returning whether anything
+    /// changed.
+    fn change(&mut self, world: &mut World, ctx: &mut ggez::Context, entry: Entry, direction: i32) -> bool {
+        let settings = &mut world.settings;
+        let toggle = |value: &mut bool| *value = !*value;
+        match entry {
+            Entry::Resolution => {
+                let current = RESOLUTIONS
+                    .iter()
+                    .position(|&(w, h)| w == settings.video.width && h == settings.video.height)
+                    .unwrap_or(0);
+                let (width, height) = RESOLUTIONS[cycle(current, RESOLUTIONS.len(), direction)];
+                settings.video.width = width;
+                settings.video.height = height;
+                apply_window(settings, ctx);
+            }
+            Entry::Fullscreen => {
+                toggle(&mut settings.video.fullscreen);
+                apply_window(settings, ctx);
+            }
+            Entry::Vsync => toggle(&mut settings.video.vsync),
+            Entry::SteeringAssist => toggle(&mut settings.assists.steering),
+            Entry::RacingLine => toggle(&mut settings.assists.racing_line),
+            Entry::Player => {
+                self.player = cycle(self.player, input::MAX_LOCAL_PLAYERS, direction);
+                return false;
+            }
+            Entry::Key(action) => {
+                if direction != 0 {
+                    return false;
+                }
+                self.capturing = Some(action);
+                world.capturing_key = true;
+                world.captured_key = None;
+                return false;
+            }
+            Entry::Back => {
+                self.back |= direction == 0;
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Binds the key the player pressed, if they have pressed one.
+    /// Returns whether the bindings changed.
+    fn bind_captured_key(&mut self, world: &mut World) -> bool {
+        let action = match self.capturing {
+            Some(action) => action,
+            None => return false,
+        };
+        let key = match world.captured_key.take() {
+            Some(key) => key,
+            None => return false,
+        };
+        self.capturing = None;
+        if key == KeyCode::Escape {
+            return false;
+        }
+        if !input::is_bindable(key) {
+            info!("{:?} can't be used for driving", key);
+            return false;
+        }
+        *action.key_mut(&mut world.settings.controls[self.player]) = input::key_name(key);
+        world.input_bindings = input::create_input_bindings(&world.settings.controls);
+        true
+    }
+}
+
+/// Steps through `n` choices, backwards for left and forwards for right
+/// or Select.
+fn cycle(current: usize, n: usize, direction: i32) -> usize {
+    let step = if direction < 0 { n - 1 } else { 1 };
+    (current + step) % n
+}
+
+/// Resizes the window to the settings.  The game keeps drawing its
+/// 800x600 view, scaled to fit.
+fn apply_window(settings: &Settings, ctx: &mut ggez::Context) {
+    let applied = graphics::set_mode(ctx, settings.window_mode())
+        .and_then(|_| graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, 800.0, 600.0)));
+    if let Err(e) = applied {
+        warn!("Couldn't change the window: {}", e);
+    }
+}
+
+impl scene::Scene<World, input::Event> for SettingsScene {
+    fn update(&mut self, gameworld: &mut World, ctx: &mut ggez::Context) -> scenes::Switch {
+        let mut changed = self.bind_captured_key(gameworld);
+        for (entry, direction) in std::mem::take(&mut self.changes) {
+            changed |= self.change(gameworld, ctx, entry, direction);
+        }
+        if changed {
+            gameworld.settings.save();
+        }
+        self.update_labels(&gameworld.settings);
+        if self.back {
+            scene::SceneSwitch::Pop
+        } else {
+            scene::SceneSwitch::None
+        }
+    }
+
+    fn draw(&mut self, _gameworld: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let mut title = graphics::Text::new("SETTINGS");
+        title.set_font(graphics::Font::default(), graphics::Scale::uniform(48.0));
+        graphics::draw(ctx, &title, graphics::DrawParam::default().dest(na::Point2::new(40.0, 20.0)))?;
+        self.list.draw_spaced(ctx, 40.0, 90.0, 28.0, 20.0)?;
+        if let Some(action) = self.capturing {
+            let text = graphics::Text::new(format!("Press a key for {:?}, or Escape to cancel", action));
+            graphics::draw(ctx, &text, graphics::DrawParam::default().dest(na::Point2::new(40.0, 570.0)))?;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "SettingsScene"
+    }
+
+    fn input(&mut self, _gameworld: &mut World, ev: input::Event, started: bool) {
+        debug!("Input: {:?}", ev);
+        if !started || self.capturing.is_some() {
+            return;
+        }
+        match ev {
+            input::Event::Button(input::Button::Back) => self.back = true,
+            input::Event::Axis(input::Axis::Horz, right) => {
+                self.changes.push((self.list.selected(), if right { 1 } else { -1 }));
+            }
+            _ => {
+                if let Some(entry) = self.list.input(&ev, started) {
+                    self.changes.push((entry, 0));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,223 @@
+//! Our scene stack, which is ggez-goodies' `SceneStack` with transitions
+//! between scenes.
+//!
+//! A scene switch starts a transition.  The scene that asked for it is
+//! frozen while the first half plays out, the switch happens halfway
+//! through, and the second half plays over the new scene.  No input gets
+//! through until it's over.
+//!
+//! Each scene on the stack gets an id, and entities tagged with it (see
+//! `World::tag`) are deleted when the scene comes off the stack.
+
+use ggez;
+use ggez::graphics;
+use ggez_goodies::scene;
+use log::*;
+
+use crate::components::SceneId;
+use crate::input;
+use crate::scenes::Switch;
+use crate::systems::DT;
+use crate::world::World;
+
+/// What a scene switch looks like, with how long it takes in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transition {
+    /// Switches straight away.
+    Cut,
+    /// Fades to black and back.
+    Fade(f32),
+    /// A black curtain sweeps across the screen from the left and then
+    /// off it to the right.
+    Wipe(f32),
+}
+
+impl Transition {
+    fn duration(self) -> f32 {
+        match self {
+            Transition::Cut => 0.0,
+            Transition::Fade(duration) | Transition::Wipe(duration) => duration,
+        }
+    }
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Transition::Fade(0.4)
+    }
+}
+
+/// A transition that's playing, and the switch it leads up to until it
+/// is halfway.
+struct Running {
+    transition: Transition,
+    elapsed: f32,
+    switch: Option<Switch>,
+}
+
+impl Running {
+    /// How far along it is, from 0.0 to 1.0.
+    fn progress(&self) -> f32 {
+        (self.elapsed / self.transition.duration()).min(1.0)
+    }
+}
+
+type Scene = Box<dyn scene::Scene<World, input::Event>>;
+
+pub struct Stack {
+    pub world: World,
+    scenes: Vec<(SceneId, Scene)>,
+    /// How switches look unless the scene asks for something else by
+    /// setting `World::transition`.
+    pub default_transition: Transition,
+    running: Option<Running>,
+}
+
+impl Stack {
+    pub fn new(_ctx: &mut ggez::Context, world: World) -> Self {
+        Stack {
+            world,
+            scenes: Vec::new(),
+            default_transition: Transition::default(),
+            running: None,
+        }
+    }
+
+    pub fn push(&mut self, scene: Scene) {
+        let id = self.world.assign_scene_id();
+        debug!("Pushing {} as scene {}", scene.name(), id);
+        self.scenes.push((id, scene))
+    }
+
+    /// Takes the top scene off the stack, and deletes its entities.
+    pub fn pop(&mut self) -> Option<Scene> {
+        let (id, scene) = self.scenes.pop()?;
+        self.world.despawn_scene(id);
+        Some(scene)
+    }
+
+    /// Whether the top scene is the named one, and gets updated rather
+    /// than waiting for a transition to finish.
+    pub fn is_updating(&self, name: &str) -> bool {
+        self.running.is_none() && self.scenes.last().map_or(false, |(_, scene)| scene.name() == name)
+    }
+
+    /// Makes the switch straight away, returning the scene it took off
+    /// the stack, if any.
+    pub fn switch(&mut self, switch: Switch) -> Option<Scene> {
+        match switch {
+            scene::SceneSwitch::None => None,
+            scene::SceneSwitch::Pop => {
+                let scene = self.pop();
+                if self.scenes.is_empty() {
+                    warn!("Popped the last scene");
+                }
+                scene
+            }
+            scene::SceneSwitch::Push(scene) => {
+                self.push(scene);
+                None
+            }
+            scene::SceneSwitch::Replace(scene) => {
+                let old = self.pop();
+                self.push(scene);
+                old
+            }
+        }
+    }
+
+    pub fn update(&mut self, ctx: &mut ggez::Context) {
+        if let Some(mut running) = self.running.take() {
+            running.elapsed += DT;
+            if running.progress() >= 0.5 {
+                if let Some(switch) = running.switch.take() {
+                    self.switch(switch);
+                }
+            }
+            if running.progress() < 1.0 {
+                self.running = Some(running);
+            }
+            return;
+        }
+
+        let switch = match self.scenes.last_mut() {
+            Some((id, scene)) => {
+                self.world.scene = *id;
+                scene.update(&mut self.world, ctx)
+            }
+            None => return,
+        };
+        if let scene::SceneSwitch::None = switch {
+            return;
+        }
+        let transition = self.world.transition.take().unwrap_or(self.default_transition);
+        if transition.duration() <= 0.0 {
+            self.switch(switch);
+        } else {
+            self.running = Some(Running { transition, elapsed: 0.0, switch: Some(switch) });
+        }
+    }
+
+    pub fn draw(&mut self, ctx: &mut ggez::Context) {
+        if let Err(e) = Stack::draw_scenes(&mut self.scenes, &mut self.world, ctx) {
+            warn!("Couldn't draw scenes: {}", e);
+        }
+        if let Some(ref running) = self.running {
+            if let Err(e) = Stack::draw_transition(running, ctx) {
+                warn!("Couldn't draw transition: {}", e);
+            }
+        }
+    }
+
+    /// Draws the top scene, and the ones under it for as long as they
+    /// want the one below drawn too.
+    fn draw_scenes(scenes: &mut [(SceneId, Scene)], world: &mut World, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        if let Some(((_, current), rest)) = scenes.split_last_mut() {
+            if current.draw_previous() {
+                Stack::draw_scenes(rest, world, ctx)?;
+            }
+            current.draw(world, ctx)?;
+        }
+        Ok(())
+    }
+
+    fn draw_transition(running: &Running, ctx: &mut ggez::Context) -> ggez::GameResult<()> {
+        let screen = graphics::screen_coordinates(ctx);
+        let t = running.progress();
+        let (rect, alpha) = match running.transition {
+            Transition::Cut => return Ok(()),
+            Transition::Fade(_) => (screen, 1.0 - (2.0 * t - 1.0).abs()),
+            Transition::Wipe(_) => {
+                // covering up to halfway, uncovering after
+                let (left, right) = if t < 0.5 { (0.0, 2.0 * t) } else { (2.0 * t - 1.0, 1.0) };
+                let rect = graphics::Rect::new(
+                    screen.x + left * screen.w,
+                    screen.y,
+                    (right - left) * screen.w,
+                    screen.h,
+                );
+                (rect, 1.0)
+            }
+        };
+        if rect.w <= 0.0 || alpha <= 0.0 {
+            return Ok(());
+        }
+        let curtain = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            rect,
+            graphics::Color::new(0.0, 0.0, 0.0, alpha),
+        )?;
+        graphics::draw(ctx, &curtain, graphics::DrawParam::default())
+    }
+
+    /// Passes the event to the top scene, unless a transition is playing.
+    pub fn input(&mut self, event: input::Event, started: bool) {
+        if self.running.is_some() {
+            return;
+        }
+        if let Some((_, scene)) = self.scenes.last_mut() {
+            scene.input(&mut self.world, event, started);
+        }
+    }
+}
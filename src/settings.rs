@@ -0,0 +1,182 @@
+//! The player's settings, saved as TOML in their config directory.
+//!
+//! These are read before the window is opened, so unlike the rest of
+//! our saved files they don't go through ggez's filesystem; they end up
+//! in the same directory anyway.
+
+use std::fs;
+use std::path::PathBuf;
+
+use directories::ProjectDirs;
+use ggez::conf;
+use log::*;
+use serde::{Deserialize, Serialize};
+
+/// What ggez knows the game as, which decides where the config
+/// directory is.
+pub const GAME_ID: &str = "game-template";
+pub const AUTHOR: &str = "ggez";
+
+const SETTINGS_FILE: &str = "settings.toml";
+
+/// Window sizes to choose from.  They all have the 4:3 shape of the
+/// game's 800x600 view, which gets scaled to fit.
+pub const RESOLUTIONS: &[(f32, f32)] = &[(800.0, 600.0), (1024.0, 768.0), (1280.0, 960.0), (1600.0, 1200.0)];
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Video {
+    pub width: f32,
+    pub height: f32,
+    pub fullscreen: bool,
+    /// Only takes effect when the game is started.
+    pub vsync: bool,
+}
+
+impl Default for Video {
+    fn default() -> Self {
+        Video { width: 800.0, height: 600.0, fullscreen: false, vsync: true }
+    }
+}
+
+/// Volumes from 0.0 (off) to 1.0.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Audio {
+    pub master: f32,
+    pub effects: f32,
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Audio { master: 1.0, effects: 1.0 }
+    }
+}
+
+/// One player's driving keys, by name, e.g. "Up", "W" or "Numpad8".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keys {
+    pub accelerate: String,
+    pub brake: String,
+    pub left: String,
+    pub right: String,
+    pub fire: String,
+    pub reset: String,
+}
+
+impl Keys {
+    fn new(keys: [&str; 6]) -> Self {
+        Keys {
+            accelerate: keys[0].to_owned(),
+            brake: keys[1].to_owned(),
+            left: keys[2].to_owned(),
+            right: keys[3].to_owned(),
+            fire: keys[4].to_owned(),
+            reset: keys[5].to_owned(),
+        }
+    }
+
+    /// The keys the game starts out with for each local player.
+    pub fn defaults() -> Vec<Keys> {
+        vec![
+            Keys::new(["Up", "Down", "Left", "Right", "Space", "R"]),
+            Keys::new(["W", "S", "A", "D", "Tab", "E"]),
+            Keys::new(["I", "K", "J", "L", "U", "O"]),
+            Keys::new(["Numpad8", "Numpad5", "Numpad4", "Numpad6", "Numpad7", "Numpad9"]),
+        ]
+    }
+}
+
+/// Help for players who want it.  The ones that change how cars handle
+/// are left off in network races, where both machines have to simulate
+/// the same thing.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Assists {
+    /// Eases off the steering at speed.
+    pub steering: bool,
+    /// Draws the racing line on the road.
+    pub racing_line: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub video: Video,
+    pub audio: Audio,
+    /// One set of keys per local player, in player order.
+    pub controls: Vec<Keys>,
+    pub assists: Assists,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            video: Video::default(),
+            audio: Audio::default(),
+            controls: Keys::defaults(),
+            assists: Assists::default(),
+        }
+    }
+}
+
+impl Settings {
+    fn path() -> Option<PathBuf> {
+        ProjectDirs::from("", AUTHOR, GAME_ID).map(|dirs| dirs.config_dir().join(SETTINGS_FILE))
+    }
+
+    /// Reads the saved settings, or the defaults if there aren't any.
+    pub fn load() -> Self {
+        let path = match Settings::path() {
+            Some(path) => path,
+            None => return Settings::default(),
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return Settings::default(),
+        };
+        let mut settings: Settings = toml::from_str(&text).unwrap_or_else(|e| {
+            warn!("Ignoring broken settings in {:?}: {}", path, e);
+            Settings::default()
+        });
+        // an old file might not have keys for every player
+        let defaults = Keys::defaults();
+        let known = settings.controls.len();
+        settings.controls.extend(defaults.into_iter().skip(known));
+        settings
+    }
+
+    pub fn save(&self) {
+        let path = match Settings::path() {
+            Some(path) => path,
+            None => return,
+        };
+        let written = toml::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|text| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+                }
+                fs::write(&path, text).map_err(|e| e.to_string())
+            });
+        if let Err(e) = written {
+            warn!("Couldn't save settings to {:?}: {}", path, e);
+        }
+    }
+
+    pub fn window_mode(&self) -> conf::WindowMode {
+        let fullscreen_type = if self.video.fullscreen {
+            conf::FullscreenType::Desktop
+        } else {
+            conf::FullscreenType::Windowed
+        };
+        conf::WindowMode::default()
+            .dimensions(self.video.width, self.video.height)
+            .fullscreen_type(fullscreen_type)
+    }
+
+    /// How loud sound effects play, all volumes considered.
+    pub fn effects_volume(&self) -> f32 {
+        self.audio.master * self.audio.effects
+    }
+}
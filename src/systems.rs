@@ -1,10 +1,989 @@
 //! specs systems.
+use crate::audio::{Sound, SoundQueue};
 use crate::components::*;
-// use crate::util;
+use crate::damage::DamageQueue;
+use crate::exam::{self, Exam, Violation, ZoneState};
+use crate::parking::ParkingChallenge;
+use crate::race::{GameMode, RaceConfig};
+use crate::track::{Rule, Track};
+use crate::types::*;
+use crate::util;
+use crate::world;
+use log::*;
 use specs::{self, Join};
 use ncollide2d as nc;
 use ggez::nalgebra as na;
 
+/// The simulation runs at a fixed timestep of this many ticks a second.
+pub const TICKS_PER_SECOND: u32 = 60;
+pub const DT: f32 = 1.0 / TICKS_PER_SECOND as f32;
+
+pub const MIN_VELOCITY: f32 = -0.5;
+pub const MAX_VELOCITY: f32 = 2.0;
+const TAU: f32 = std::f32::consts::PI * 2.0;
+
+/// Radians full steering turns a car each tick.
+const STEERING_RATE: f32 = 0.05;
+/// Speed full throttle adds to a car each tick.
+const THROTTLE_RATE: f32 = 0.05;
+
+/// How well a car drives with no health left, as a fraction of new.
+const WRECKED_PERFORMANCE: f32 = 0.4;
+
+/// Applies each vehicle's `Controls` to its `Motion`, the same way for
+/// the player and for AI drivers.  Damaged cars go slower and steer
+/// worse, wrecked ones don't go at all, and cars off the road may be
+/// speed limited.
+pub struct VehicleSystem;
+
+impl<'a> specs::System<'a> for VehicleSystem {
+    type SystemData = (
+        specs::ReadStorage<'a, Controls>,
+        specs::ReadStorage<'a, Handling>,
+        specs::ReadStorage<'a, Health>,
+        specs::ReadStorage<'a, Wrecked>,
+        specs::ReadStorage<'a, OffTrack>,
+        specs::WriteStorage<'a, Motion>,
+    );
+
+    fn run(&mut self, (controls, handling, healths, wrecked, off_tracks, mut motions): Self::SystemData) {
+        let default_handling = Handling::default();
+        for (controls, handling, health, wrecked, off_track, motion) in (&controls, handling.maybe(), healths.maybe(), wrecked.maybe(), off_tracks.maybe(), &mut motions).join() {
+            if wrecked.is_some() {
+                motion.acceleration.y = 0.0;
+                motion.update();
+                continue;
+            }
+
+            let handling = handling.unwrap_or(&default_handling);
+            let condition = health.map_or(1.0, |h| WRECKED_PERFORMANCE + (1.0 - WRECKED_PERFORMANCE) * h.fraction());
+            let limit = off_track.and_then(|o| o.speed_limit).unwrap_or(1.0);
+            let top_speed = handling.top_speed * condition * limit;
+            if !motion.is_blocked {
+                motion.orientation += controls.steering * STEERING_RATE * handling.steering * condition;
+                if motion.orientation < 0.0 {
+                    motion.orientation += TAU;
+                } else if motion.orientation > TAU {
+                    motion.orientation -= TAU;
+                }
+
+                // this is y-velocity, not acceleration
+                motion.acceleration.y += controls.throttle * THROTTLE_RATE;
+                motion.acceleration.y = motion.acceleration.y
+                    .max(MIN_VELOCITY * top_speed)
+                    .min(MAX_VELOCITY * top_speed);
+            }
+            motion.update();
+        }
+    }
+}
+
+/// Seconds a wrecked car waits before it respawns.
+const WRECK_RESPAWN_TIME: f32 = 3.0;
+
+/// Applies queued damage to each target's `Health`, wrecking vehicles
+/// that run out.
+pub struct DamageSystem;
+
+impl<'a> specs::System<'a> for DamageSystem {
+    type SystemData = (
+        specs::Write<'a, DamageQueue>,
+        specs::ReadStorage<'a, Invulnerable>,
+        specs::WriteStorage<'a, Health>,
+        specs::WriteStorage<'a, Wrecked>,
+    );
+
+    fn run(&mut self, (mut damage, invulnerable, mut healths, mut wrecked): Self::SystemData) {
+        for event in damage.events.drain(..) {
+            if wrecked.get(event.target).is_some() || invulnerable.get(event.target).is_some() {
+                continue;
+            }
+            if let Some(health) = healths.get_mut(event.target) {
+                health.current = (health.current - event.amount).max(0.0);
+                if health.current <= 0.0 {
+                    debug!("{:?} was wrecked by {:?}", event.target, event.source);
+                    wrecked.insert(event.target, Wrecked { respawn_timer: WRECK_RESPAWN_TIME }).expect("couldn't insert Wrecked");
+                }
+            }
+        }
+    }
+}
+
+/// Counts down wrecked vehicles' respawn timers, and repairs and
+/// respawns them when the time is up.
+pub struct WreckSystem;
+
+impl<'a> specs::System<'a> for WreckSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::WriteStorage<'a, Wrecked>,
+        specs::WriteStorage<'a, Health>,
+        specs::WriteStorage<'a, Respawn>,
+    );
+
+    fn run(&mut self, (entities, mut wrecked, mut healths, mut respawns): Self::SystemData) {
+        let mut repaired = Vec::new();
+        for (entity, wreck) in (&entities, &mut wrecked).join() {
+            wreck.respawn_timer -= DT;
+            if wreck.respawn_timer <= 0.0 {
+                repaired.push(entity);
+            }
+        }
+        for entity in repaired {
+            wrecked.remove(entity);
+            if let Some(health) = healths.get_mut(entity) {
+                health.current = health.max;
+            }
+            if let Some(respawn) = respawns.get_mut(entity) {
+                respawn.requested = true;
+            }
+        }
+    }
+}
+
+/// Seconds a car may stand still, or stay off the track, before it is
+/// put back automatically.
+const STUCK_RESPAWN_TIME: f32 = 5.0;
+const OFF_TRACK_RESPAWN_TIME: f32 = 3.0;
+/// Slower than this counts as standing still.
+const STUCK_SPEED: f32 = 0.05;
+/// Further than this from the racing line counts as off the track.
+const OFF_TRACK_DISTANCE: f32 = 192.0;
+/// Seconds a respawned car can't be damaged for.
+const RESPAWN_INVULNERABILITY: f32 = 2.0;
+
+/// Moves each car's respawn point up as it passes checkpoints, and puts
+/// cars back there when asked to, or when they are stuck or off the track.
+pub struct RespawnSystem;
+
+impl<'a> specs::System<'a> for RespawnSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::Read<'a, Track>,
+        specs::ReadStorage<'a, Controls>,
+        specs::ReadStorage<'a, Wrecked>,
+        specs::WriteStorage<'a, Respawn>,
+        specs::WriteStorage<'a, Position>,
+        specs::WriteStorage<'a, Motion>,
+        specs::WriteStorage<'a, Invulnerable>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, track, controls, wrecked, mut respawns, mut positions, mut motions, mut invulnerable) = data;
+
+        let mut expired = Vec::new();
+        for (entity, inv) in (&entities, &mut invulnerable).join() {
+            inv.remaining -= DT;
+            if inv.remaining <= 0.0 {
+                expired.push(entity);
+            }
+        }
+        for entity in expired {
+            invulnerable.remove(entity);
+        }
+
+        let mut respawned = Vec::new();
+        for (entity, respawn, pos, motion) in (&entities, &mut respawns, &mut positions, &mut motions).join() {
+            if let Some(checkpoint) = track.checkpoints.get(respawn.next_checkpoint) {
+                if checkpoint.contains(pos.point) {
+                    respawn.point = checkpoint.position;
+                    respawn.orientation = checkpoint.orientation;
+                    respawn.next_checkpoint = (respawn.next_checkpoint + 1) % track.checkpoints.len();
+                }
+            }
+
+            // wrecks sit out their timer rather than counting as stuck
+            if wrecked.get(entity).is_none() {
+                if controls.get(entity).map_or(false, |c| c.reset) {
+                    respawn.requested = true;
+                }
+                if motion.acceleration.y.abs() < STUCK_SPEED || motion.is_blocked {
+                    respawn.stuck_time += DT;
+                } else {
+                    respawn.stuck_time = 0.0;
+                }
+                let line = &track.racing_line;
+                if !line.is_empty() && (line.sample(line.project(pos.point)) - pos.point).length() > OFF_TRACK_DISTANCE {
+                    respawn.off_track_time += DT;
+                } else {
+                    respawn.off_track_time = 0.0;
+                }
+                if respawn.stuck_time > STUCK_RESPAWN_TIME || respawn.off_track_time > OFF_TRACK_RESPAWN_TIME {
+                    respawn.requested = true;
+                }
+            }
+
+            if respawn.requested {
+                respawn.requested = false;
+                respawn.stuck_time = 0.0;
+                respawn.off_track_time = 0.0;
+                pos.point = respawn.point;
+                pos.rotation = respawn.orientation;
+                motion.orientation = respawn.orientation;
+                motion.acceleration = util::vec2(0.0, 0.0);
+                motion.is_blocked = false;
+                motion.update();
+                respawned.push(entity);
+            }
+        }
+        for entity in respawned {
+            invulnerable.insert(entity, Invulnerable { remaining: RESPAWN_INVULNERABILITY }).expect("couldn't insert Invulnerable");
+        }
+    }
+}
+
+/// Seconds of driving the wrong way before a car is flagged for it.
+const WRONG_WAY_GRACE: f32 = 1.5;
+/// Driving at more than this angle to the track direction counts as the
+/// wrong way, as long as the car is moving faster than WRONG_WAY_SPEED.
+const WRONG_WAY_ANGLE: f32 = std::f32::consts::PI * 0.6;
+const WRONG_WAY_SPEED: f32 = 0.2;
+
+/// Tracks each car's `RaceProgress` along the racing line, counting a
+/// lap whenever it wraps past the start and timing the car until it
+/// has done the race's laps, and notices cars driving the wrong way.
+pub struct RaceProgressSystem;
+
+impl<'a> specs::System<'a> for RaceProgressSystem {
+    type SystemData = (
+        specs::Read<'a, Track>,
+        specs::Read<'a, RaceConfig>,
+        specs::ReadStorage<'a, Position>,
+        specs::ReadStorage<'a, Motion>,
+        specs::ReadStorage<'a, Respawn>,
+        specs::ReadStorage<'a, OffTrack>,
+        specs::WriteStorage<'a, RaceProgress>,
+    );
+
+    fn run(&mut self, (track, config, positions, motions, respawns, off_tracks, mut progresses): Self::SystemData) {
+        let line = &track.racing_line;
+        let length = line.length();
+        let cars = (&positions, &motions, respawns.maybe(), off_tracks.maybe(), &mut progresses);
+        for (pos, motion, respawn, off_track, progress) in cars.join() {
+            if !progress.finished {
+                progress.time += DT;
+            }
+            // the way the track goes here, from the racing line if there is
+            // one, otherwise towards the next checkpoint
+            let track_direction = if !line.is_empty() {
+                let distance = line.project(pos.point);
+                if progress.started && line.closed {
+                    // a jump of more than half the track means we wrapped around
+                    let delta = distance - progress.distance;
+                    if delta < -length / 2.0 {
+                        progress.lap += 1;
+                    } else if delta > length / 2.0 {
+                        progress.lap -= 1;
+                    }
+                }
+                progress.started = true;
+                progress.distance = distance;
+                progress.total = progress.lap as f32 * length + distance;
+                if progress.lap > progress.lap_reached && !progress.finished {
+                    // starting lap 1 only starts the clock on it
+                    if progress.lap_reached >= 1 {
+                        let completed = progress.lap_reached;
+                        progress.laps.push(Lap {
+                            time: progress.time - progress.lap_start,
+                            // track limits haven't moved on to the new lap yet
+                            valid: off_track.map_or(true, |o| o.invalid_lap != Some(completed)),
+                        });
+                    }
+                    progress.lap_reached = progress.lap;
+                    progress.lap_start = progress.time;
+                }
+                if config.mode == GameMode::Race && progress.lap > config.laps && !progress.finished {
+                    progress.finished = true;
+                    debug!("Finished in {:.2}s", progress.time);
+                }
+                Some(line.tangent(distance))
+            } else {
+                respawn
+                    .and_then(|r| track.checkpoints.get(r.next_checkpoint))
+                    .map(|c| c.position - pos.point)
+                    .filter(|d| d.length() > 0.0)
+                    .map(|d| d.normalize())
+            };
+
+            let speed = motion.acceleration.y;
+            let going_backwards = track_direction.map_or(false, |direction| {
+                let travel = util::heading(motion.orientation) * speed.signum();
+                speed.abs() > WRONG_WAY_SPEED && travel.dot(direction) < WRONG_WAY_ANGLE.cos()
+            });
+            if going_backwards {
+                progress.wrong_way_time += DT;
+            } else if speed.abs() > WRONG_WAY_SPEED {
+                progress.wrong_way_time = 0.0;
+            }
+            progress.wrong_way = progress.wrong_way_time > WRONG_WAY_GRACE;
+            if progress.wrong_way {
+                progress.wrong_way_total += DT;
+            }
+        }
+    }
+}
+
+/// Where a car's wheels are, in pixels from its centre: half the
+/// distance between the left and right ones, and the front and back ones.
+const WHEEL_TRACK: f32 = 16.0;
+const WHEEL_BASE: f32 = 28.0;
+
+/// Measures how long each car spends with all four wheels off the road,
+/// and hands out the race's track limit penalties for it.
+pub struct TrackLimitsSystem;
+
+impl<'a> specs::System<'a> for TrackLimitsSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::Read<'a, Track>,
+        specs::Read<'a, RaceConfig>,
+        specs::ReadStorage<'a, Position>,
+        specs::ReadStorage<'a, Motion>,
+        specs::ReadStorage<'a, RaceProgress>,
+        specs::ReadStorage<'a, Wrecked>,
+        specs::WriteStorage<'a, OffTrack>,
+    );
+
+    fn run(&mut self, (entities, track, config, positions, motions, progresses, wrecked, mut off_tracks): Self::SystemData) {
+        for (entity, pos, motion, progress, off_track) in (&entities, &positions, &motions, progresses.maybe(), &mut off_tracks).join() {
+            off_track.moved |= motion.acceleration.y.abs() > 0.0;
+            let forward = util::heading(pos.rotation);
+            let right = util::vec2(-forward.y, forward.x);
+            let wheels = [
+                pos.point + forward * WHEEL_BASE + right * WHEEL_TRACK,
+                pos.point + forward * WHEEL_BASE - right * WHEEL_TRACK,
+                pos.point - forward * WHEEL_BASE + right * WHEEL_TRACK,
+                pos.point - forward * WHEEL_BASE - right * WHEEL_TRACK,
+            ];
+            off_track.off = off_track.moved && wrecked.get(entity).is_none() && wheels.iter().all(|w| !track.is_drivable(*w));
+            if off_track.off {
+                off_track.time += DT;
+                off_track.total += DT;
+            } else {
+                off_track.time = 0.0;
+            }
+
+            let lap = progress.map_or(0, |p| p.lap);
+            if off_track.invalid_lap.map_or(false, |invalid| invalid != lap) {
+                off_track.invalid_lap = None;
+            }
+
+            off_track.speed_limit = None;
+            let rules = match config.track_limits {
+                Some(ref rules) => rules,
+                None => continue,
+            };
+            if off_track.off {
+                if off_track.time > rules.grace {
+                    if off_track.time - DT <= rules.grace {
+                        info!("{:?} is off the track, time penalty applies", entity);
+                    }
+                    off_track.penalty += DT * rules.time_penalty;
+                }
+                if rules.invalidate_after.map_or(false, |after| off_track.time > after) && off_track.invalid_lap.is_none() {
+                    info!("{:?} lap {} invalidated for leaving the track", entity, lap);
+                    off_track.invalid_lap = Some(lap);
+                }
+                off_track.speed_limit = rules.speed_limit;
+            }
+        }
+    }
+}
+
+/// Cars closer to the stop line than this when the light turns yellow
+/// keep going rather than brake hard.
+const SIGNAL_COMMIT_DISTANCE: f32 = 64.0;
+/// Cars start braking for a stop line this far before it.
+const SIGNAL_BRAKE_DISTANCE: f32 = 192.0;
+
+/// Runs the traffic lights' cycles, and notes which cars drive onto a
+/// stop line while its light is red.
+pub struct TrafficSignalSystem;
+
+impl<'a> specs::System<'a> for TrafficSignalSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::ReadStorage<'a, Position>,
+        specs::ReadStorage<'a, Motion>,
+        specs::ReadStorage<'a, Controls>,
+        specs::WriteStorage<'a, TrafficSignal>,
+    );
+
+    fn run(&mut self, (entities, positions, motions, controls, mut signals): Self::SystemData) {
+        for signal in (&mut signals).join() {
+            let cycle = signal.cycle_length();
+            if cycle > 0.0 {
+                signal.time = (signal.time + DT) % cycle;
+            }
+            let red = signal.state() == SignalState::Red;
+
+            let mut inside = Vec::new();
+            signal.ran_red.clear();
+            for (entity, pos, motion, _) in (&entities, &positions, &motions, &controls).join() {
+                if !signal.stop_line.contains(pos.point) {
+                    continue;
+                }
+                if red && signal.controls(motion.orientation) && !signal.inside.contains(&entity) {
+                    debug!("{:?} ran a red light at {:?}", entity, pos.point);
+                    signal.ran_red.push(entity);
+                }
+                inside.push(entity);
+            }
+            signal.inside = inside;
+        }
+    }
+}
+
+/// Whether the car drove onto a stop line on red this tick.
+pub fn ran_red_light(signals: &specs::ReadStorage<TrafficSignal>, entity: specs::Entity) -> bool {
+    signals.join().any(|signal| signal.ran_red.contains(&entity))
+}
+
+/// How far ahead the nearest stop line a car at `point` facing
+/// `orientation` has to stop at is, if any.  Yellow lights only count
+/// when there is still room to stop.
+pub fn signal_stop_distance(signals: &specs::ReadStorage<TrafficSignal>, point: Point2, orientation: f32) -> Option<f32> {
+    signals
+        .join()
+        .filter_map(|signal| {
+            let distance = signal.distance_ahead(point, orientation)?;
+            match signal.state() {
+                SignalState::Green => None,
+                SignalState::Yellow if distance < SIGNAL_COMMIT_DISTANCE => None,
+                _ => Some(distance),
+            }
+        })
+        .fold(None, |nearest: Option<f32>, d| Some(nearest.map_or(d, |n| n.min(d))))
+}
+
+/// Slower than this counts as not moving, for parking.
+const PARKED_SPEED: f32 = 0.02;
+
+/// Runs the parking challenge for the player: times it, counts bumps and
+/// changes of direction, and finishes once the car has been held still
+/// in the bay for long enough.
+pub struct ParkingSystem;
+
+impl<'a> specs::System<'a> for ParkingSystem {
+    type SystemData = (
+        specs::Read<'a, Track>,
+        specs::Read<'a, RaceConfig>,
+        specs::Write<'a, ParkingChallenge>,
+        specs::ReadStorage<'a, Player>,
+        specs::ReadStorage<'a, Position>,
+        specs::ReadStorage<'a, Motion>,
+    );
+
+    fn run(&mut self, (track, config, mut parking, players, positions, motions): Self::SystemData) {
+        if config.mode != GameMode::Parking || parking.finished {
+            return;
+        }
+        let bay = match track.parking_bays.first() {
+            Some(bay) => bay,
+            None => return,
+        };
+        parking.elapsed += DT;
+        // the challenge is for one driver
+        let (_, pos, motion) = match (&players, &positions, &motions).join().next() {
+            Some(driver) => driver,
+            None => return,
+        };
+
+        if motion.is_blocked && !parking.was_blocked {
+            parking.collisions += 1;
+        }
+        parking.was_blocked = motion.is_blocked;
+
+        let speed = motion.acceleration.y;
+        if speed.abs() > PARKED_SPEED {
+            let direction = speed.signum();
+            if parking.last_direction != 0.0 && direction != parking.last_direction {
+                parking.direction_changes += 1;
+            }
+            parking.last_direction = direction;
+        }
+
+        if speed.abs() <= PARKED_SPEED && bay.accepts(pos.point, motion.orientation) {
+            parking.held += DT;
+        } else {
+            parking.held = 0.0;
+        }
+        if parking.held >= bay.hold_time {
+            parking.finished = true;
+            info!(
+                "Parked in {:.1}s with {} collisions and {} direction changes, score {}",
+                parking.elapsed, parking.collisions, parking.direction_changes, parking.score()
+            );
+        }
+    }
+}
+
+/// A car going faster than this within YIELD_DISTANCE of a yield zone
+/// has right of way over one entering it faster than YIELD_SPEED.
+const YIELD_DISTANCE: f32 = 256.0;
+const YIELD_SPEED: f32 = 0.3;
+const ONCOMING_SPEED: f32 = 0.1;
+
+/// Examines the player in the driving test, logging a `Violation` every
+/// time they break one of the map's rules.  The test is over after one
+/// lap, or as soon as they have failed or run out of time.
+pub struct ExamSystem;
+
+impl<'a> specs::System<'a> for ExamSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::Read<'a, Track>,
+        specs::Read<'a, RaceConfig>,
+        specs::Write<'a, Exam>,
+        specs::ReadStorage<'a, Player>,
+        specs::ReadStorage<'a, Shot>,
+        specs::ReadStorage<'a, Position>,
+        specs::ReadStorage<'a, Motion>,
+        specs::ReadStorage<'a, RaceProgress>,
+        specs::ReadStorage<'a, TrafficSignal>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, track, config, mut exam, players, shots, positions, motions, progresses, signals) = data;
+        if config.mode != GameMode::Exam || exam.finished {
+            return;
+        }
+        exam.zones.resize(track.rules.len(), ZoneState::default());
+
+        exam.elapsed += DT;
+        // the test is for one driver
+        let driver = (&entities, &players, &positions, &motions, progresses.maybe()).join().next();
+        let (entity, _, pos, motion, progress) = match driver {
+            Some(driver) => driver,
+            None => return,
+        };
+        let speed = motion.acceleration.y.abs();
+        let mut broken = Vec::new();
+        for (zone, state) in track.rules.iter().zip(exam.zones.iter_mut()) {
+            let inside = zone.area.contains(pos.point);
+            let entered = inside && !state.inside;
+            let left = !inside && state.inside;
+            match zone.rule {
+                Rule::SpeedLimit(limit) => {
+                    if inside && speed > limit {
+                        if !state.flagged {
+                            broken.push(zone.rule);
+                        }
+                        state.flagged = true;
+                    } else {
+                        state.flagged = false;
+                    }
+                }
+                Rule::StopLine => {
+                    if inside && speed < PARKED_SPEED {
+                        state.stopped = true;
+                    }
+                    if left {
+                        if !state.stopped {
+                            broken.push(zone.rule);
+                        }
+                        state.stopped = false;
+                    }
+                }
+                Rule::NoEntry => {
+                    if entered {
+                        broken.push(zone.rule);
+                    }
+                }
+                Rule::Yield => {
+                    let oncoming = (&entities, &positions, &motions).join().any(|(other, other_pos, other_motion)| {
+                        other != entity
+                            && shots.get(other).is_none()
+                            && other_motion.acceleration.y.abs() > ONCOMING_SPEED
+                            && (other_pos.point - zone.area.position).length() < YIELD_DISTANCE
+                    });
+                    if entered && speed > YIELD_SPEED && oncoming {
+                        broken.push(zone.rule);
+                    }
+                }
+                // checked at the traffic lights below, and by the level
+                Rule::RedLight | Rule::HitPedestrian => (),
+            }
+            state.inside = inside;
+        }
+        if ran_red_light(&signals, entity) {
+            broken.push(Rule::RedLight);
+        }
+
+        for rule in broken {
+            let violation = Violation { time: exam.elapsed, position: pos.point, rule };
+            info!("Exam: {} at {:.1}s, {:?}", violation.description(), violation.time, violation.position);
+            exam.violations.push(violation);
+        }
+        // the grid is behind the start line, so lap 1 starts on the
+        // first crossing and the lap is done when lap 2 starts
+        exam.timed_out = exam.elapsed >= exam::TIME_LIMIT;
+        if exam.timed_out || exam.points() >= exam::FAIL_POINTS || progress.map_or(false, |p| p.lap > 1) {
+            exam.finished = true;
+            info!("Exam over with {} points, {}", exam.points(), if exam.passed() { "passed" } else { "failed" });
+        }
+    }
+}
+
+const AI_STEERING_GAIN: f32 = 2.0;
+const AI_THROTTLE_GAIN: f32 = 4.0;
+/// The slowest an AI car will take a turn, as a fraction of its top speed.
+const AI_MIN_CORNER_SPEED: f32 = 0.3;
+/// How far off the racing line, in pixels, the least accurate driver wanders.
+const AI_MAX_WANDER: f32 = 48.0;
+/// How far ahead AI drivers look for other cars, plus a bit more per unit of speed.
+const AI_SCAN_DISTANCE: f32 = 128.0;
+const AI_SCAN_PER_SPEED: f32 = 48.0;
+/// AI drivers start braking for something closer than this, and stop
+/// if it is within AI_STOP_DISTANCE.
+const AI_BRAKE_DISTANCE: f32 = 112.0;
+const AI_STOP_DISTANCE: f32 = 48.0;
+/// Sideways offset of the overtaking lanes either side of the racing line.
+const AI_LANE_OFFSET: f32 = 64.0;
+/// How many pixels a tick an AI car moves towards its chosen lane.
+const AI_LANE_SHIFT_RATE: f32 = 1.5;
+/// Seconds of clear road before an AI car heads back to the racing line.
+const AI_RETURN_DELAY: f32 = 1.0;
+
+/// Casts a ray through the collision world and returns the distance to
+/// and entity of the nearest thing it hits, ignoring `ignore`.
+/// `direction` must be a unit vector.
+pub fn cast_ray(
+    collision_world: &nc::world::CollisionWorld<f32, specs::Entity>,
+    origin: Point2,
+    direction: Vector2,
+    max_distance: f32,
+    ignore: specs::Entity,
+) -> Option<(f32, specs::Entity)> {
+    let ray = nc::query::Ray::new(na::Point2::new(origin.x, origin.y), na::Vector2::new(direction.x, direction.y));
+    // looking from a car, which pedestrians don't blacklist the way they
+    // do each other
+    let groups = nc::pipeline::object::CollisionGroups::new()
+        .with_membership(&[world::CAR_GROUP])
+        .with_whitelist(&[world::CAR_GROUP, world::PEDESTRIAN_GROUP]);
+    collision_world
+        .interferences_with_ray(&ray, &groups)
+        .filter(|(_, obj, inter)| *obj.data() != ignore && inter.toi <= max_distance)
+        .map(|(_, obj, inter)| (inter.toi, *obj.data()))
+        .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+}
+
+/// Steers and throttles `AiDriver` cars along the track's racing line,
+/// driving as well as their `AiProfile` allows.
+pub struct AiDriverSystem;
+
+impl<'a> specs::System<'a> for AiDriverSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::Read<'a, Track>,
+        specs::Read<'a, RaceConfig>,
+        specs::ReadExpect<'a, nc::world::CollisionWorld<f32, specs::Entity>>,
+        specs::ReadStorage<'a, Position>,
+        specs::ReadStorage<'a, Motion>,
+        specs::ReadStorage<'a, Player>,
+        specs::ReadStorage<'a, RaceProgress>,
+        specs::ReadStorage<'a, TrafficSignal>,
+        specs::WriteStorage<'a, AiDriver>,
+        specs::WriteStorage<'a, Controls>,
+        specs::WriteStorage<'a, Handling>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, track, config, collision_world, positions, motions, players, progresses, signals, mut drivers, mut controls, mut handling) = data;
+        let line = &track.racing_line;
+
+        // rubber-banding is measured against the leading player
+        let leader = (&players, &progresses).join().map(|(_, p)| p.total).fold(None, |best: Option<f32>, t| {
+            Some(best.map_or(t, |b| b.max(t)))
+        });
+
+        for (entity, pos, motion, driver, controls) in (&entities, &positions, &motions, &mut drivers, &mut controls).join() {
+            driver.ticks += 1;
+            let profile = &driver.profile;
+
+            let mut top_speed = profile.top_speed_fraction;
+            if let (Some(band), Some(leader), Some(progress)) = (&config.rubber_band, leader, progresses.get(entity)) {
+                top_speed *= band.speed_factor(leader - progress.total);
+            }
+            if let Some(handling) = handling.get_mut(entity) {
+                handling.top_speed = top_speed;
+            }
+
+            let decision = if line.is_empty() {
+                Controls::default()
+            } else {
+                let progress = line.project(pos.point);
+                let heading = util::heading(motion.orientation);
+                let speed = motion.acceleration.y;
+
+                // look for something in the way, and pick a clear lane to pass it
+                let scan = AI_SCAN_DISTANCE + speed.max(0.0) * AI_SCAN_PER_SPEED;
+                let ahead = cast_ray(&collision_world, pos.point, heading, scan, entity);
+                let side = util::vec2(-heading.y, heading.x);
+                let lane_offset = driver.lane_offset;
+                let lane_is_clear = |offset: f32| {
+                    cast_ray(&collision_world, pos.point + side * (offset - lane_offset), heading, scan, entity).is_none()
+                };
+                if ahead.is_some() {
+                    driver.clear_time = 0.0;
+                    if driver.target_lane == driver.lane_offset || !lane_is_clear(driver.target_lane) {
+                        driver.target_lane = if lane_is_clear(-AI_LANE_OFFSET) {
+                            -AI_LANE_OFFSET
+                        } else if lane_is_clear(AI_LANE_OFFSET) {
+                            AI_LANE_OFFSET
+                        } else {
+                            driver.lane_offset
+                        };
+                    }
+                } else if driver.target_lane != 0.0 {
+                    driver.clear_time += DT;
+                    if driver.clear_time > AI_RETURN_DELAY && lane_is_clear(0.0) {
+                        driver.target_lane = 0.0;
+                    }
+                }
+                let shift = (driver.target_lane - driver.lane_offset).max(-AI_LANE_SHIFT_RATE).min(AI_LANE_SHIFT_RATE);
+                driver.lane_offset += shift;
+
+                // less accurate drivers drift from side to side of the line
+                let target_distance = progress + driver.lookahead;
+                let tangent = line.tangent(target_distance);
+                let phase = driver.ticks as f32 * DT + entity.id() as f32;
+                let wander = (1.0 - profile.line_accuracy) * AI_MAX_WANDER * phase.sin();
+                let target = line.sample(target_distance) + util::vec2(-tangent.y, tangent.x) * (wander + driver.lane_offset);
+
+                let error = util::wrap_angle(util::orientation_of(target - pos.point) - motion.orientation);
+                let steering = (error * AI_STEERING_GAIN).max(-1.0).min(1.0);
+
+                // gentle drivers look further ahead for corners to brake for
+                let brake_distance = progress + driver.lookahead * (3.0 - 2.0 * profile.braking_aggression);
+                let bend = util::wrap_angle(util::orientation_of(line.tangent(brake_distance)) - motion.orientation);
+                let sharpness = error.abs().max(bend.abs()) / std::f32::consts::PI;
+                let min_corner_speed = AI_MIN_CORNER_SPEED + 0.3 * profile.braking_aggression;
+                let mut target_speed = MAX_VELOCITY * top_speed * (1.0 - sharpness).max(min_corner_speed);
+                // turn around tightly after spinning out
+                if progresses.get(entity).map_or(false, |p| p.wrong_way) {
+                    target_speed = target_speed.min(MAX_VELOCITY * AI_MIN_CORNER_SPEED);
+                }
+                if let Some(line_speed) = line.target_speed(brake_distance) {
+                    target_speed = target_speed.min(line_speed * top_speed);
+                }
+
+                // slow down behind whatever is still in the way, matching its speed
+                if let Some((distance, other)) = ahead {
+                    if distance < AI_BRAKE_DISTANCE && driver.lane_offset == driver.target_lane {
+                        let room = ((distance - AI_STOP_DISTANCE) / (AI_BRAKE_DISTANCE - AI_STOP_DISTANCE)).max(0.0);
+                        let other_speed = motions.get(other).map_or(0.0, |m| m.acceleration.y.max(0.0));
+                        target_speed = target_speed.min(other_speed.max(target_speed * room));
+                    }
+                }
+                // and stop at red lights
+                if let Some(distance) = signal_stop_distance(&signals, pos.point, motion.orientation) {
+                    target_speed = target_speed.min(target_speed * stopping_room(distance));
+                }
+                let throttle = ((target_speed - speed) * AI_THROTTLE_GAIN).max(-1.0).min(1.0);
+
+                Controls { steering, throttle, ..Controls::default() }
+            };
+
+            // act on decisions made reaction_delay ago
+            driver.pending.push_back(decision);
+            let delay_ticks = (driver.profile.reaction_delay * TICKS_PER_SECOND as f32) as usize;
+            while driver.pending.len() > delay_ticks {
+                if let Some(c) = driver.pending.pop_front() {
+                    *controls = c;
+                }
+            }
+        }
+    }
+}
+
+/// How much of its speed a car should keep with a stop line `distance`
+/// ahead: all of it far away, none once it is AI_STOP_DISTANCE away.
+fn stopping_room(distance: f32) -> f32 {
+    ((distance - AI_STOP_DISTANCE) / (SIGNAL_BRAKE_DISTANCE - AI_STOP_DISTANCE)).max(0.0).min(1.0)
+}
+
+/// Seconds a pedestrian waits at the side of the road before crossing.
+const PEDESTRIAN_MIN_WAIT: f32 = 2.0;
+const PEDESTRIAN_MAX_WAIT: f32 = 6.0;
+
+/// Walks pedestrians across the road, waiting a random while at each
+/// side before heading back.
+pub struct PedestrianSystem;
+
+impl<'a> specs::System<'a> for PedestrianSystem {
+    type SystemData = (
+        specs::Write<'a, util::Rng>,
+        specs::WriteStorage<'a, Pedestrian>,
+        specs::WriteStorage<'a, Position>,
+    );
+
+    fn run(&mut self, (mut rng, mut pedestrians, mut positions): Self::SystemData) {
+        for (pedestrian, pos) in (&mut pedestrians, &mut positions).join() {
+            if pedestrian.wait > 0.0 {
+                pedestrian.wait -= DT;
+                continue;
+            }
+            let length = (pedestrian.to - pedestrian.from).length();
+            if length > 0.0 {
+                pedestrian.progress += pedestrian.speed * DT / length;
+            }
+            if length <= 0.0 || pedestrian.progress >= 1.0 {
+                std::mem::swap(&mut pedestrian.from, &mut pedestrian.to);
+                pedestrian.progress = 0.0;
+                pedestrian.wait = rng.range(PEDESTRIAN_MIN_WAIT, PEDESTRIAN_MAX_WAIT);
+            }
+            pos.point = pedestrian.from.lerp(pedestrian.to, pedestrian.progress);
+        }
+    }
+}
+
+const TRAFFIC_LOOKAHEAD: f32 = 80.0;
+/// Traffic stops for anything closer than this.
+const TRAFFIC_STOP_DISTANCE: f32 = 96.0;
+/// Seconds the player can block a traffic car before it honks, and
+/// between honks.
+const TRAFFIC_HONK_DELAY: f32 = 1.0;
+const TRAFFIC_HONK_COOLDOWN: f32 = 3.0;
+
+/// Drives ambient `Traffic` cars along their lanes, stopping for anything
+/// in the way and honking at players who block them.
+pub struct TrafficSystem;
+
+impl<'a> specs::System<'a> for TrafficSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::Read<'a, Track>,
+        specs::ReadExpect<'a, nc::world::CollisionWorld<f32, specs::Entity>>,
+        specs::Write<'a, SoundQueue>,
+        specs::ReadStorage<'a, Position>,
+        specs::ReadStorage<'a, Motion>,
+        specs::ReadStorage<'a, Player>,
+        specs::ReadStorage<'a, TrafficSignal>,
+        specs::WriteStorage<'a, Traffic>,
+        specs::WriteStorage<'a, Controls>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, track, collision_world, mut sounds, positions, motions, players, signals, mut traffic, mut controls) = data;
+        for (entity, pos, motion, car, controls) in (&entities, &positions, &motions, &mut traffic, &mut controls).join() {
+            let lane = match track.traffic_lanes.get(car.lane) {
+                Some(lane) if !lane.is_empty() => lane,
+                _ => {
+                    *controls = Controls::default();
+                    continue;
+                }
+            };
+
+            let target = lane.sample(lane.project(pos.point) + TRAFFIC_LOOKAHEAD);
+            let error = util::wrap_angle(util::orientation_of(target - pos.point) - motion.orientation);
+            controls.steering = (error * AI_STEERING_GAIN).max(-1.0).min(1.0);
+
+            let heading = util::heading(motion.orientation);
+            let blocker = cast_ray(&collision_world, pos.point, heading, TRAFFIC_STOP_DISTANCE, entity).map(|(_, e)| e);
+            let mut target_speed = if blocker.is_some() { 0.0 } else { car.cruise_speed };
+            if let Some(distance) = signal_stop_distance(&signals, pos.point, motion.orientation) {
+                target_speed *= stopping_room(distance);
+            }
+            controls.throttle = ((target_speed - motion.acceleration.y) * AI_THROTTLE_GAIN).max(-1.0).min(1.0);
+
+            car.honk_cooldown = (car.honk_cooldown - DT).max(0.0);
+            if blocker.map_or(false, |e| players.get(e).is_some()) {
+                car.blocked_time += DT;
+                if car.blocked_time > TRAFFIC_HONK_DELAY && car.honk_cooldown <= 0.0 {
+                    sounds.play(Sound::Horn, pos.point);
+                    car.honk_cooldown = TRAFFIC_HONK_COOLDOWN;
+                }
+            } else {
+                car.blocked_time = 0.0;
+            }
+        }
+    }
+}
+
+const SHOT_SPEED: f32 = 6.0;
+const SHOT_RADIUS: f32 = 4.0;
+const SHOT_DAMAGE: u32 = 10;
+/// Seconds a shot flies for, and between shots.
+const SHOT_LIFETIME: f32 = 1.5;
+const FIRE_DELAY: f32 = 0.25;
+/// How far in front of the car's centre shots appear.
+const MUZZLE_DISTANCE: f32 = 44.0;
+
+/// Fires shots from cars whose driver is holding the fire control.
+pub struct WeaponSystem;
+
+impl<'a> specs::System<'a> for WeaponSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::WriteExpect<'a, nc::world::CollisionWorld<f32, specs::Entity>>,
+        specs::ReadStorage<'a, Controls>,
+        specs::WriteStorage<'a, Weapon>,
+        specs::WriteStorage<'a, Position>,
+        specs::WriteStorage<'a, Motion>,
+        specs::WriteStorage<'a, Shot>,
+        specs::WriteStorage<'a, Lifetime>,
+        specs::WriteStorage<'a, Collider>,
+        specs::WriteStorage<'a, SceneTag>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (entities, mut collision_world, controls, mut weapons, mut positions, mut motions, mut shots, mut lifetimes, mut colliders, mut tags) = data;
+
+        let mut fired = Vec::new();
+        for (entity, controls, weapon, pos, motion) in (&entities, &controls, &mut weapons, &positions, &motions).join() {
+            weapon.cooldown = (weapon.cooldown - DT).max(0.0);
+            if controls.fire && weapon.cooldown <= 0.0 {
+                weapon.cooldown = FIRE_DELAY;
+                let point = pos.point + util::heading(motion.orientation) * MUZZLE_DISTANCE;
+                let speed = SHOT_SPEED + motion.acceleration.y.max(0.0);
+                fired.push((entity, weapon.group, point, motion.orientation, speed));
+            }
+        }
+
+        for (owner, owner_group, point, orientation, speed) in fired {
+            let mut motion = Motion { velocity: util::vec2(0.0, 0.0), acceleration: util::vec2(0.0, speed), is_blocked: false, orientation };
+            motion.update();
+            let shot = entities.build_entity()
+                .with(Position { point, rotation: orientation }, &mut positions)
+                .with(motion, &mut motions)
+                .with(Shot { damage: SHOT_DAMAGE, owner }, &mut shots)
+                .with(Lifetime { remaining: SHOT_LIFETIME }, &mut lifetimes)
+                .build();
+            // a shot goes with the scene of the car that fired it
+            if let Some(tag) = tags.get(owner).cloned() {
+                tags.insert(shot, tag).expect("couldn't insert SceneTag");
+            }
+
+            // shots are sensors, so they report hits without bouncing anything
+            let groups = nc::pipeline::object::CollisionGroups::new()
+                .with_membership(&[world::SHOT_GROUP])
+                .with_blacklist(&[world::SHOT_GROUP, world::PEDESTRIAN_GROUP, owner_group]);
+            let (handle, _) = collision_world.add(
+                na::Isometry2::new(na::Vector2::new(point.x, point.y), orientation),
+                nc::shape::ShapeHandle::new(nc::shape::Ball::new(SHOT_RADIUS)),
+                groups,
+                nc::pipeline::object::GeometricQueryType::Proximity(0.0),
+                shot,
+            );
+            colliders.insert(shot, Collider { handle }).expect("couldn't insert Collider");
+        }
+    }
+}
+
+/// Counts down every `Lifetime`; the scene despawns the ones that run out.
+pub struct LifetimeSystem;
+
+impl<'a> specs::System<'a> for LifetimeSystem {
+    type SystemData = specs::WriteStorage<'a, Lifetime>;
+
+    fn run(&mut self, mut lifetimes: Self::SystemData) {
+        for lifetime in (&mut lifetimes).join() {
+            lifetime.remaining -= DT;
+        }
+    }
+}
+
 pub struct MovementSystem;
 
 impl<'a> specs::System<'a> for MovementSystem {
@@ -16,7 +995,7 @@ impl<'a> specs::System<'a> for MovementSystem {
     fn run(&mut self, (mut pos, motion): Self::SystemData) {
         for (pos, motion) in (&mut pos, &motion).join() {
             let mut screen_velocity = motion.velocity;
-            screen_velocity.y = screen_velocity.y * -1.0;
+            screen_velocity.y *= -1.0;
             pos.point += screen_velocity;
             pos.rotation = motion.orientation;
         }
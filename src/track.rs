@@ -0,0 +1,627 @@
+//! Course data read from the layers of a Tiled map.
+
+use std::path::Path;
+
+use ggez_goodies::tilemap::tiled;
+use log::*;
+
+use crate::components::{SignalState, TrafficSignal};
+use crate::types::*;
+use crate::util;
+
+/// Name of the tile layer holding the road; any tile in it is drivable.
+const COURSE_LAYER: &str = "Course";
+/// Name of the object layer holding the racing line.
+const RACING_LINE_LAYER: &str = "racing_line";
+/// Name of the object layer holding the lanes ambient traffic drives along.
+const TRAFFIC_LAYER: &str = "traffic";
+/// Name of the object layer holding the checkpoint rectangles, in order.
+const CHECKPOINTS_LAYER: &str = "checkpoints";
+
+/// Name of the object layer holding parking bays, for the parking challenge.
+const PARKING_LAYER: &str = "parking";
+
+/// Name of the object layer holding the traffic rule zones checked in
+/// the driving test.
+const RULES_LAYER: &str = "rules";
+
+/// Name of the object layer holding traffic light stop lines.
+const SIGNALS_LAYER: &str = "signals";
+
+/// Name of the object layer holding pedestrian crossings.
+const CROSSINGS_LAYER: &str = "crossings";
+
+/// How many straight pieces each spline segment gets flattened into.
+const SUBDIVISIONS: usize = 8;
+
+/// Everything the game systems need to know about the course.  Lives in
+/// the specs world as a resource.
+#[derive(Clone, Debug, Default)]
+pub struct Track {
+    pub racing_line: Spline,
+    pub traffic_lanes: Vec<Spline>,
+    pub checkpoints: Vec<Checkpoint>,
+    /// Which tiles are road.  Maps without a course layer count as road
+    /// everywhere.
+    pub course: Option<TileGrid>,
+    pub parking_bays: Vec<ParkingBay>,
+    pub rules: Vec<RuleZone>,
+    /// The traffic lights as they are at the start; the level spawns an
+    /// entity for each.
+    pub signals: Vec<TrafficSignal>,
+    /// The two ends of each pedestrian crossing.
+    pub crossings: Vec<(Point2, Point2)>,
+}
+
+impl Track {
+    pub fn from_tiled(map: &tiled::Map) -> Self {
+        let mut track = Track::default();
+        if let Some(layer) = map.layers.iter().find(|l| l.name == COURSE_LAYER) {
+            track.course = Some(TileGrid::from_layer(map, layer));
+        }
+        for group in &map.object_groups {
+            if group.name == RACING_LINE_LAYER {
+                // Only one racing line is supported, the first one wins.
+                if let Some(spline) = group.objects.iter().filter_map(spline_from_object).next() {
+                    track.racing_line = spline;
+                }
+            } else if group.name == TRAFFIC_LAYER {
+                track.traffic_lanes.extend(group.objects.iter().filter_map(spline_from_object));
+            } else if group.name == CHECKPOINTS_LAYER {
+                let mut checkpoints: Vec<_> = group.objects.iter().enumerate()
+                    .filter_map(|(i, object)| {
+                        let index = property_f32(&object.properties, "index").unwrap_or(i as f32);
+                        Checkpoint::from_object(object).map(|c| (index, c))
+                    })
+                    .collect();
+                checkpoints.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+                track.checkpoints = checkpoints.into_iter().map(|(_, c)| c).collect();
+            } else if group.name == PARKING_LAYER {
+                track.parking_bays.extend(group.objects.iter().filter_map(ParkingBay::from_object));
+            } else if group.name == RULES_LAYER {
+                track.rules.extend(group.objects.iter().filter_map(RuleZone::from_object));
+            } else if group.name == SIGNALS_LAYER {
+                track.signals.extend(group.objects.iter().filter_map(signal_from_object));
+            } else if group.name == CROSSINGS_LAYER {
+                track.crossings.extend(group.objects.iter().filter_map(|object| match object.shape {
+                    tiled::ObjectShape::Polyline { ref points } => {
+                        let points = object_points(object, points);
+                        Some((*points.first()?, *points.last()?))
+                    }
+                    _ => None,
+                }));
+            }
+        }
+
+        // respawned cars face along the racing line
+        for checkpoint in &mut track.checkpoints {
+            if !track.racing_line.is_empty() {
+                let distance = track.racing_line.project(checkpoint.position);
+                checkpoint.orientation = util::orientation_of(track.racing_line.tangent(distance));
+            }
+        }
+        if track.racing_line.is_empty() {
+            warn!("Map has no polyline in a {:?} layer, AI cars will not drive", RACING_LINE_LAYER);
+        }
+        track
+    }
+
+    /// Whether the point is on a road tile.
+    pub fn is_drivable(&self, point: Point2) -> bool {
+        self.course.as_ref().map_or(true, |course| course.is_set(point))
+    }
+}
+
+/// What the level select shows about a map, from the `name`,
+/// `description` and `author` properties of the map itself.
+#[derive(Clone, Debug)]
+pub struct MapInfo {
+    /// File name, relative to the resource directory.
+    pub file: String,
+    pub name: String,
+    pub description: String,
+    pub author: String,
+}
+
+impl MapInfo {
+    pub fn from_tiled(file: &str, map: &tiled::Map) -> Self {
+        let property = |key: &str| property_string(&map.properties, key).unwrap_or_default();
+        let name = property_string(&map.properties, "name").unwrap_or_else(|| file.trim_end_matches(".tmx").to_owned());
+        MapInfo {
+            file: file.to_owned(),
+            name,
+            description: property("description"),
+            author: property("author"),
+        }
+    }
+
+    /// Reads every `.tmx` file in the directory, sorted by file name.
+    /// Ones that don't parse are left out.
+    pub fn discover(dir: &Path) -> Vec<MapInfo> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("Couldn't look for maps in {:?}: {}", dir, e);
+                return Vec::new();
+            }
+        };
+        let mut files: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|file| file.ends_with(".tmx"))
+            .collect();
+        files.sort();
+        files
+            .into_iter()
+            .filter_map(|file| match tiled::parse_file(&dir.join(&file)) {
+                Ok(map) => Some(MapInfo::from_tiled(&file, &map)),
+                Err(e) => {
+                    warn!("Skipping map {}: {:?}", file, e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// One flag per tile of a tile layer, set where the layer has a tile.
+#[derive(Clone, Debug)]
+pub struct TileGrid {
+    pub width: usize,
+    pub height: usize,
+    pub tile_width: f32,
+    pub tile_height: f32,
+    set: Vec<bool>,
+}
+
+impl TileGrid {
+    fn from_layer(map: &tiled::Map, layer: &tiled::Layer) -> Self {
+        let (width, height) = (map.width as usize, map.height as usize);
+        let mut set = vec![false; width * height];
+        for (y, row) in layer.tiles.iter().enumerate().take(height) {
+            for (x, tile) in row.iter().enumerate().take(width) {
+                set[y * width + x] = *tile != 0;
+            }
+        }
+        TileGrid {
+            width,
+            height,
+            tile_width: map.tile_width as f32,
+            tile_height: map.tile_height as f32,
+            set,
+        }
+    }
+
+    /// Whether the tile under the point is set.  Points off the map never are.
+    pub fn is_set(&self, point: Point2) -> bool {
+        if point.x < 0.0 || point.y < 0.0 {
+            return false;
+        }
+        let (x, y) = ((point.x / self.tile_width) as usize, (point.y / self.tile_height) as usize);
+        x < self.width && y < self.height && self.set[y * self.width + x]
+    }
+}
+
+/// A rectangle cars have to drive through in order; the last one they
+/// passed is where they respawn.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    /// The centre of the rectangle.
+    pub position: Point2,
+    pub half_width: f32,
+    pub half_height: f32,
+    /// Rotation of the rectangle, in radians clockwise.
+    pub rotation: f32,
+    /// Which way a car respawned here should face.
+    pub orientation: f32,
+}
+
+impl Checkpoint {
+    /// Reads a rectangle object.  Tiled rotates rectangles around their
+    /// top-left corner.
+    fn from_object(object: &tiled::Object) -> Option<Self> {
+        let (width, height) = match object.shape {
+            tiled::ObjectShape::Rect { width, height } => (width, height),
+            _ => return None,
+        };
+        let rotation = object.rotation.to_radians();
+        let (sin, cos) = rotation.sin_cos();
+        let (hw, hh) = (width / 2.0, height / 2.0);
+        Some(Checkpoint {
+            position: util::point2(object.x + hw * cos - hh * sin, object.y + hw * sin + hh * cos),
+            half_width: hw,
+            half_height: hh,
+            rotation,
+            orientation: rotation,
+        })
+    }
+
+    pub fn contains(&self, point: Point2) -> bool {
+        let d = point - self.position;
+        let (sin, cos) = self.rotation.sin_cos();
+        let local_x = d.x * cos + d.y * sin;
+        let local_y = -d.x * sin + d.y * cos;
+        local_x.abs() <= self.half_width && local_y.abs() <= self.half_height
+    }
+}
+
+/// A rectangle a car has to be parked in, facing the way the rectangle
+/// is rotated, and held there for a while.
+#[derive(Clone, Debug)]
+pub struct ParkingBay {
+    pub area: Checkpoint,
+    /// How far, in pixels, the car's centre may be from the bay's.
+    pub position_tolerance: f32,
+    /// How far, in radians, the car may be turned from the bay's rotation.
+    pub angle_tolerance: f32,
+    /// Seconds the car has to stay parked.
+    pub hold_time: f32,
+}
+
+impl ParkingBay {
+    /// Reads a rectangle object.  The optional `position_tolerance`,
+    /// `angle_tolerance` (in degrees) and `hold_time` properties override
+    /// the defaults.
+    fn from_object(object: &tiled::Object) -> Option<Self> {
+        let area = Checkpoint::from_object(object)?;
+        let properties = &object.properties;
+        Some(ParkingBay {
+            area,
+            position_tolerance: property_f32(properties, "position_tolerance").unwrap_or(12.0),
+            angle_tolerance: property_f32(properties, "angle_tolerance").unwrap_or(8.0).to_radians(),
+            hold_time: property_f32(properties, "hold_time").unwrap_or(2.0),
+        })
+    }
+
+    /// Whether a car at `point` facing `orientation` is parked in the bay.
+    pub fn accepts(&self, point: Point2, orientation: f32) -> bool {
+        (point - self.area.position).length() <= self.position_tolerance
+            && util::wrap_angle(orientation - self.area.rotation).abs() <= self.angle_tolerance
+    }
+}
+
+/// A traffic rule that applies inside a rectangle of the map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    /// Cars must not go faster than this.
+    SpeedLimit(f32),
+    /// Cars must come to a stop before leaving the area.
+    StopLine,
+    /// Cars must not drive into the area at all.
+    NoEntry,
+    /// Cars must not drive in while another car is coming.
+    Yield,
+    /// Cars must not cross a traffic light's stop line on red.  This one
+    /// comes from the signals layer rather than a rule zone.
+    RedLight,
+    /// Cars must not hit pedestrians, anywhere.
+    HitPedestrian,
+}
+
+#[derive(Clone, Debug)]
+pub struct RuleZone {
+    pub rule: Rule,
+    pub area: Checkpoint,
+}
+
+impl RuleZone {
+    /// Reads a rectangle object whose type is `speed_limit` (with a
+    /// `limit` property), `stop_line`, `no_entry` or `yield`.
+    fn from_object(object: &tiled::Object) -> Option<Self> {
+        let rule = match object.obj_type.as_str() {
+            "speed_limit" => match property_f32(&object.properties, "limit") {
+                Some(limit) => Rule::SpeedLimit(limit),
+                None => {
+                    warn!("Speed limit zone {:?} has no limit property", object.name);
+                    return None;
+                }
+            },
+            "stop_line" => Rule::StopLine,
+            "no_entry" => Rule::NoEntry,
+            "yield" => Rule::Yield,
+            other => {
+                warn!("Unknown rule {:?} for zone {:?}", other, object.name);
+                return None;
+            }
+        };
+        Checkpoint::from_object(object).map(|area| RuleZone { rule, area })
+    }
+}
+
+/// A Catmull-Rom spline through a list of control points.  It is
+/// flattened into short straight pieces when built, so that it can be
+/// sampled by distance along it.
+#[derive(Clone, Debug, Default)]
+pub struct Spline {
+    pub closed: bool,
+    samples: Vec<Point2>,
+    /// Distance along the spline of each sample; the last one is the length.
+    distances: Vec<f32>,
+    speeds: Vec<Option<f32>>,
+}
+
+impl Spline {
+    /// `speeds` holds an optional target speed for each control point;
+    /// speeds in between are interpolated.
+    pub fn new(points: &[Point2], speeds: &[Option<f32>], closed: bool) -> Self {
+        let mut spline = Spline { closed, ..Spline::default() };
+        if points.is_empty() {
+            return spline;
+        }
+
+        let n = points.len() as isize;
+        let point_at = |i: isize| -> Vector2 {
+            let i = if closed { ((i % n) + n) % n } else { i.max(0).min(n - 1) };
+            points[i as usize].to_vector()
+        };
+        let speed_at = |i: isize| -> Option<f32> {
+            let i = if closed { i % n } else { i.min(n - 1) };
+            speeds.get(i as usize).cloned().unwrap_or(None)
+        };
+
+        let segments = if closed { n } else { n - 1 };
+        for i in 0..segments {
+            let (p0, p1, p2, p3) = (point_at(i - 1), point_at(i), point_at(i + 1), point_at(i + 2));
+            for step in 0..SUBDIVISIONS {
+                let t = step as f32 / SUBDIVISIONS as f32;
+                spline.push(catmull_rom(p0, p1, p2, p3, t).to_point(), lerp_speed(speed_at(i), speed_at(i + 1), t));
+            }
+        }
+        let last = if closed { 0 } else { n - 1 };
+        spline.push(point_at(last).to_point(), speed_at(last));
+        spline
+    }
+
+    fn push(&mut self, point: Point2, speed: Option<f32>) {
+        let distance = match self.samples.last() {
+            Some(prev) => self.length() + (point - *prev).length(),
+            None => 0.0,
+        };
+        self.samples.push(point);
+        self.distances.push(distance);
+        self.speeds.push(speed);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// The straight pieces the spline was flattened into.
+    pub fn samples(&self) -> &[Point2] {
+        &self.samples
+    }
+
+    pub fn length(&self) -> f32 {
+        self.distances.last().cloned().unwrap_or(0.0)
+    }
+
+    /// Brings a distance into 0..length, wrapping around closed splines
+    /// and clamping open ones.
+    pub fn wrap(&self, distance: f32) -> f32 {
+        let length = self.length();
+        if length <= 0.0 {
+            0.0
+        } else if self.closed {
+            ((distance % length) + length) % length
+        } else {
+            distance.max(0.0).min(length)
+        }
+    }
+
+    /// Returns the index of the straight piece containing `distance`
+    /// and how far along that piece it is, from 0.0 to 1.0.
+    fn locate(&self, distance: f32) -> (usize, f32) {
+        let distance = self.wrap(distance);
+        let i = match self.distances.binary_search_by(|d| d.partial_cmp(&distance).unwrap()) {
+            Ok(i) => i,
+            Err(i) => i.max(1) - 1,
+        };
+        let i = i.min(self.samples.len().max(2) - 2);
+        let piece = self.distances[i + 1] - self.distances[i];
+        let t = if piece > 0.0 { (distance - self.distances[i]) / piece } else { 0.0 };
+        (i, t)
+    }
+
+    /// The point at the given distance along the spline.
+    pub fn sample(&self, distance: f32) -> Point2 {
+        if self.samples.len() < 2 {
+            return self.samples.first().cloned().unwrap_or_else(|| util::point2(0.0, 0.0));
+        }
+        let (i, t) = self.locate(distance);
+        self.samples[i].lerp(self.samples[i + 1], t)
+    }
+
+    /// The unit direction of travel at the given distance along the spline.
+    pub fn tangent(&self, distance: f32) -> Vector2 {
+        if self.samples.len() < 2 {
+            return util::heading(0.0);
+        }
+        let (i, _) = self.locate(distance);
+        let d = self.samples[i + 1] - self.samples[i];
+        if d.length() > 0.0 {
+            d.normalize()
+        } else {
+            util::heading(0.0)
+        }
+    }
+
+    /// The target speed at the given distance along the spline, if the
+    /// map specified one.
+    pub fn target_speed(&self, distance: f32) -> Option<f32> {
+        if self.samples.len() < 2 {
+            return self.speeds.first().cloned().unwrap_or(None);
+        }
+        let (i, t) = self.locate(distance);
+        lerp_speed(self.speeds[i], self.speeds[i + 1], t)
+    }
+
+    /// The distance along the spline of the point on it closest to `point`.
+    pub fn project(&self, point: Point2) -> f32 {
+        let mut best = (std::f32::MAX, 0.0);
+        for i in 0..self.samples.len().max(1) - 1 {
+            let (a, b) = (self.samples[i], self.samples[i + 1]);
+            let ab = b - a;
+            let piece = ab.square_length();
+            let t = if piece > 0.0 { ((point - a).dot(ab) / piece).max(0.0).min(1.0) } else { 0.0 };
+            let dist = (a.lerp(b, t) - point).square_length();
+            if dist < best.0 {
+                best = (dist, self.distances[i] + t * (self.distances[i + 1] - self.distances[i]));
+            }
+        }
+        best.1
+    }
+}
+
+fn catmull_rom(p0: Vector2, p1: Vector2, p2: Vector2, p3: Vector2, t: f32) -> Vector2 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+fn lerp_speed(a: Option<f32>, b: Option<f32>, t: f32) -> Option<f32> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + (b - a) * t),
+        (Some(a), None) => Some(a),
+        (None, b) => b,
+    }
+}
+
+/// Reads a numeric property, accepting both int and float values.
+pub fn property_f32(properties: &tiled::Properties, key: &str) -> Option<f32> {
+    match properties.get(key) {
+        Some(tiled::PropertyValue::FloatValue(v)) => Some(*v),
+        Some(tiled::PropertyValue::IntValue(v)) => Some(*v as f32),
+        _ => None,
+    }
+}
+
+pub fn property_string(properties: &tiled::Properties, key: &str) -> Option<String> {
+    match properties.get(key) {
+        Some(tiled::PropertyValue::StringValue(v)) => Some(v.clone()),
+        _ => None,
+    }
+}
+
+/// Converts object-relative polyline points to map coordinates, applying
+/// the object's (clockwise, in degrees) rotation.
+fn object_points(object: &tiled::Object, points: &[(f32, f32)]) -> Vec<Point2> {
+    let (sin, cos) = object.rotation.to_radians().sin_cos();
+    points
+        .iter()
+        .map(|&(x, y)| util::point2(object.x + x * cos - y * sin, object.y + x * sin + y * cos))
+        .collect()
+}
+
+/// Builds a traffic light from a stop line rectangle.  The `green`,
+/// `yellow` and `red` properties set how long each phase lasts in
+/// seconds, `offset` how far into the cycle the light starts, and
+/// `direction` (in degrees, defaulting to the object's rotation) which
+/// way the cars it controls are driving.
+fn signal_from_object(object: &tiled::Object) -> Option<TrafficSignal> {
+    let stop_line = Checkpoint::from_object(object)?;
+    let properties = &object.properties;
+    let phases = vec![
+        (SignalState::Green, property_f32(properties, "green").unwrap_or(5.0)),
+        (SignalState::Yellow, property_f32(properties, "yellow").unwrap_or(2.0)),
+        (SignalState::Red, property_f32(properties, "red").unwrap_or(5.0)),
+    ];
+    let mut signal = TrafficSignal {
+        direction: property_f32(properties, "direction").map_or(stop_line.rotation, f32::to_radians),
+        stop_line,
+        phases,
+        time: 0.0,
+        inside: Vec::new(),
+        ran_red: Vec::new(),
+    };
+    let cycle = signal.cycle_length();
+    if cycle > 0.0 {
+        signal.time = property_f32(properties, "offset").unwrap_or(0.0) % cycle;
+    }
+    Some(signal)
+}
+
+/// Builds a spline from a `polyline` or `polygon` object.  A `speed`
+/// property sets the target speed for the whole line, and `speed_N`
+/// properties override it for the Nth point.
+fn spline_from_object(object: &tiled::Object) -> Option<Spline> {
+    let (points, closed) = match object.shape {
+        tiled::ObjectShape::Polyline { ref points } => (object_points(object, points), false),
+        tiled::ObjectShape::Polygon { ref points } => (object_points(object, points), true),
+        _ => return None,
+    };
+    let default_speed = property_f32(&object.properties, "speed");
+    let speeds: Vec<_> = (0..points.len())
+        .map(|i| property_f32(&object.properties, &format!("speed_{}", i)).or(default_speed))
+        .collect();
+    Some(Spline::new(&points, &speeds, closed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 0.001;
+
+    /// A closed loop through the corners of a 100 pixel square, going
+    /// clockwise on screen.
+    fn square() -> Spline {
+        let corners = [util::point2(0.0, 0.0), util::point2(100.0, 0.0), util::point2(100.0, 100.0), util::point2(0.0, 100.0)];
+        Spline::new(&corners, &[], true)
+    }
+
+    fn assert_near(a: Point2, b: Point2) {
+        assert!((a - b).length() < EPSILON, "{:?} is not {:?}", a, b);
+    }
+
+    #[test]
+    fn closed_splines_wrap_around() {
+        let spline = square();
+        let length = spline.length();
+        assert!(length > 400.0);
+        assert!((spline.wrap(-10.0) - (length - 10.0)).abs() < EPSILON);
+        assert!((spline.wrap(length + 10.0) - 10.0).abs() < EPSILON);
+        assert_near(spline.sample(length + 30.0), spline.sample(30.0));
+        assert_near(spline.sample(length), util::point2(0.0, 0.0));
+    }
+
+    #[test]
+    fn open_splines_stop_at_the_ends() {
+        let spline = Spline::new(&[util::point2(0.0, 0.0), util::point2(100.0, 0.0)], &[], false);
+        assert!((spline.length() - 100.0).abs() < EPSILON);
+        assert_eq!(spline.wrap(-10.0), 0.0);
+        assert_eq!(spline.wrap(110.0), 100.0);
+        assert_near(spline.sample(150.0), util::point2(100.0, 0.0));
+    }
+
+    #[test]
+    fn projects_onto_the_square() {
+        let spline = square();
+        let corners: Vec<f32> = [util::point2(0.0, 0.0), util::point2(100.0, 0.0), util::point2(100.0, 100.0)]
+            .iter()
+            .map(|&corner| spline.project(corner))
+            .collect();
+        assert_near(spline.sample(corners[1]), util::point2(100.0, 0.0));
+        assert_near(spline.sample(corners[2]), util::point2(100.0, 100.0));
+        assert!(corners[0] < EPSILON);
+        assert!(corners[0] < corners[1] && corners[1] < corners[2]);
+
+        // beside the middle of the top side, outside the square
+        let top = spline.project(util::point2(50.0, -20.0));
+        assert!(corners[0] < top && top < corners[1]);
+        assert!((spline.sample(top).x - 50.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn tangents_point_the_way_round() {
+        let spline = square();
+        let top = spline.project(util::point2(50.0, 0.0));
+        let right = spline.project(util::point2(100.0, 50.0));
+        assert!(spline.tangent(top).dot(util::vec2(1.0, 0.0)) > 0.9);
+        assert!(spline.tangent(right).dot(util::vec2(0.0, 1.0)) > 0.9);
+        assert!((spline.tangent(top).length() - 1.0).abs() < EPSILON);
+    }
+}
@@ -2,6 +2,94 @@
 pub use euclid::point2;
 pub use euclid::vec2;
 
+use crate::types::Vector2;
+
+/// The unit vector (in screen coordinates) a car with the given
+/// orientation drives along.  Orientation 0 points up the screen and
+/// increases clockwise.
+pub fn heading(orientation: f32) -> Vector2 {
+    vec2(orientation.sin(), -orientation.cos())
+}
+
+/// The orientation that points along the given screen-space direction;
+/// the inverse of `heading`.
+pub fn orientation_of(direction: Vector2) -> f32 {
+    direction.x.atan2(-direction.y)
+}
+
+/// Wraps an angle difference into the range -PI..PI.
+pub fn wrap_angle(angle: f32) -> f32 {
+    use std::f32::consts::PI;
+    let mut a = angle % (PI * 2.0);
+    if a > PI {
+        a -= PI * 2.0;
+    } else if a < -PI {
+        a += PI * 2.0;
+    }
+    a
+}
+
+/// A small xorshift random number generator.  The simulation uses this
+/// rather than a thread-local RNG so that a run can be repeated from the
+/// same seed.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck on zero
+        Rng(seed.max(1))
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 32) as u32
+    }
+
+    /// A number in 0.0..1.0.
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    pub fn range(&mut self, low: f32, high: f32) -> f32 {
+        low + (high - low) * self.next_f32()
+    }
+
+    /// An index in 0..len; len must not be zero.
+    pub fn index(&mut self, len: usize) -> usize {
+        self.next_u32() as usize % len
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::new(0x9E37_79B9_7F4A_7C15)
+    }
+}
+
+/// Reads a TOML file from ggez's filesystem.
+pub fn read_toml<T>(ctx: &mut ggez::Context, path: &str) -> ggez::GameResult<T>
+where
+    T: for<'de> serde::Deserialize<'de>,
+{
+    use std::io::Read;
+    let mut text = String::new();
+    ggez::filesystem::open(ctx, path)?.read_to_string(&mut text)?;
+    toml::from_str(&text).map_err(|e| ggez::GameError::ResourceLoadError(format!("{}: {}", path, e)))
+}
+
+/// Writes a TOML file to ggez's filesystem, i.e. the user's data
+/// directory.
+pub fn write_toml<T: serde::Serialize>(ctx: &mut ggez::Context, path: &str, value: &T) -> ggez::GameResult<()> {
+    use std::io::Write;
+    let text = toml::to_string(value).map_err(|e| ggez::GameError::ResourceLoadError(format!("{}: {}", path, e)))?;
+    let mut file = ggez::filesystem::create(ctx, path)?;
+    file.write_all(text.as_bytes())?;
+    Ok(())
+}
+
 /// Basic logging setup to log to the console with `fern`.
 pub fn setup_logging() {
     use fern::colors::{Color, ColoredLevelConfig};
@@ -1,21 +1,64 @@
-use crate::{components, input, resources};
+use crate::{components, input, resources, scenes, settings, track};
 
 use log::*;
-use specs::{self};
-use warmy;
+use specs::{self, Join};
 use ncollide2d as nc;
 
 use std::path;
 
+/// Collision groups, used to filter what can touch what.
+pub const CAR_GROUP: usize = 0;
+pub const SHOT_GROUP: usize = 1;
+pub const PEDESTRIAN_GROUP: usize = 2;
+/// Each armed car gets a group of its own, from this one up, so that its
+/// shots can pass through it.
+pub const FIRST_SHOOTER_GROUP: usize = 3;
+
+/// Puts a resource back the way it was before a scene added it.
+type ResetResource = fn(&mut specs::World);
+
 pub struct World {
     pub resources: resources::Store,
-    pub input: input::State,
+    /// Where maps and the like are read from.
+    pub resource_dir: path::PathBuf,
+    /// The maps found in the resource directory, once something has
+    /// asked for them, see `maps`.
+    maps: Option<Vec<track::MapInfo>>,
+    pub settings: settings::Settings,
+    /// One binding per local player, in player order, made from the
+    /// settings.
+    pub input_bindings: Vec<input::Binding>,
+    /// Set while the settings wait for a key to bind; the next key
+    /// pressed goes to `captured_key` instead of the game.
+    pub capturing_key: bool,
+    pub captured_key: Option<ggez::event::KeyCode>,
+    /// One input state per local player.  The first player's also drives
+    /// the menus.
+    pub inputs: Vec<input::State>,
+    /// Each player's input for the coming tick, from `inputs` or from the
+    /// network.
+    pub frames: Vec<input::Frame>,
+    /// Set for a tick when a race over the network has to wait for the
+    /// other machine's input, so the level holds still.
+    pub waiting_for_peer: bool,
+    /// Set once the other machine in a race over the network has gone.
+    pub peer_gone: bool,
+    /// Set by a scene to choose how its next switch looks.
+    pub transition: Option<scenes::Transition>,
+    /// The scene entities spawned now belong to, see `tag`.
+    pub scene: components::SceneId,
+    next_scene: components::SceneId,
+    /// What to reset when each scene leaves the stack, see
+    /// `add_scene_resource`.
+    scene_resources: Vec<(components::SceneId, ResetResource)>,
+    /// Set by the pause menu for the level to act on.
+    pub pause_choice: Option<scenes::pause::PauseChoice>,
     pub specs_world: specs::World
     // pub collide_world: nc::world::CollisionWorld<f32, specs::Entity>
 }
 
 impl World {
-    pub fn new(resource_dir: &path::Path) -> Self {
+    pub fn new(resource_dir: &path::Path, settings: settings::Settings) -> Self {
         // We to bridge the gap between ggez and warmy path
         // handling here; ggez assumes its own absolute paths, warmy
         // assumes system-absolute paths; so, we make warmy look in
@@ -37,13 +80,100 @@ impl World {
 
         w.add_resource(cw);
 
-        let the_world = Self {
+        Self {
             resources: store,
-            input: input::State::new(),
+            resource_dir: resource_dir.to_owned(),
+            maps: None,
+            input_bindings: input::create_input_bindings(&settings.controls),
+            settings,
+            capturing_key: false,
+            captured_key: None,
+            inputs: (0..input::MAX_LOCAL_PLAYERS).map(|_| input::State::new()).collect(),
+            frames: vec![input::Frame::default(); input::MAX_LOCAL_PLAYERS],
+            waiting_for_peer: false,
+            peer_gone: false,
+            transition: None,
+            scene: 0,
+            next_scene: 0,
+            scene_resources: Vec::new(),
+            pause_choice: None,
             specs_world: w
             // collide_world: cw
+        }
+    }
+
+    /// The maps in the resource directory.  They are only looked for the
+    /// first time, so maps added while the game runs don't show up.
+    pub fn maps(&mut self) -> &[track::MapInfo] {
+        let resource_dir = &self.resource_dir;
+        self.maps.get_or_insert_with(|| track::MapInfo::discover(resource_dir))
+    }
+
+    /// The id the next scene pushed onto the stack will get.  A scene
+    /// that spawns entities while it's being made, before it's on the
+    /// stack, makes them for this one.
+    pub fn upcoming_scene(&self) -> components::SceneId {
+        self.next_scene
+    }
+
+    /// Gives out the id for a scene going onto the stack.
+    pub fn assign_scene_id(&mut self) -> components::SceneId {
+        let id = self.next_scene;
+        self.next_scene += 1;
+        id
+    }
+
+    /// Marks the entity as belonging to the current scene.
+    pub fn tag(&mut self, entity: specs::Entity) {
+        let tag = components::SceneTag { scene: self.scene };
+        self.specs_world.write_storage::<components::SceneTag>().insert(entity, tag).expect("couldn't insert SceneTag");
+    }
+
+    /// Adds a resource that belongs to the current scene.  It goes back
+    /// to its default when the scene leaves the stack.
+    pub fn add_scene_resource<T: specs::shred::Resource + Default>(&mut self, resource: T) {
+        fn reset<T: specs::shred::Resource + Default>(world: &mut specs::World) {
+            world.add_resource(T::default());
+        }
+        self.specs_world.add_resource(resource);
+        self.scene_resources.push((self.scene, reset::<T>));
+    }
+
+    /// Deletes every entity that belongs to the scene, along with their
+    /// collision objects, and resets its resources.
+    pub fn despawn_scene(&mut self, scene: components::SceneId) {
+        let owned: Vec<specs::Entity> = {
+            let entities = self.specs_world.entities();
+            let tags = self.specs_world.read_storage::<components::SceneTag>();
+            (&entities, &tags).join().filter(|(_, tag)| tag.scene == scene).map(|(e, _)| e).collect()
         };
+        if !owned.is_empty() {
+            debug!("Despawning {} entities of scene {}", owned.len(), scene);
+        }
+        for entity in owned {
+            self.despawn(entity);
+        }
+        self.specs_world.maintain();
+
+        let specs_world = &mut self.specs_world;
+        self.scene_resources.retain(|&(owner, reset)| {
+            if owner == scene {
+                reset(specs_world);
+            }
+            owner != scene
+        });
+    }
 
-        the_world
+    /// Deletes an entity along with its collision object, if it has one.
+    pub fn despawn(&mut self, entity: specs::Entity) {
+        let collider = self.specs_world.read_storage::<components::Collider>().get(entity).cloned();
+        if let Some(collider) = collider {
+            self.specs_world
+                .write_resource::<nc::world::CollisionWorld<f32, specs::Entity>>()
+                .remove(&[collider.handle]);
+        }
+        if let Err(e) = self.specs_world.delete_entity(entity) {
+            warn!("Tried to despawn a dead entity: {:?}", e);
+        }
     }
 }